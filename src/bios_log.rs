@@ -0,0 +1,185 @@
+use blob::{BlobReader, ParseError};
+use event_log::{EventType, PcrEvent};
+use trousers_sys::tspi::TSS_VERSION;
+
+/// Where Linux exposes the firmware event log of the first TPM.
+pub const BIOS_MEASUREMENTS_PATH: &str = "/sys/kernel/security/tpm0/binary_bios_measurements";
+
+const SPEC_ID_EVENT03_SIGNATURE: &[u8] = b"Spec ID Event03\0";
+
+/// Parses a firmware event log made up of TCG 1.2
+/// `TCG_PCClientPCREventStruct` entries, as found at
+/// `BIOS_MEASUREMENTS_PATH`.
+///
+/// TPM 2.0 crypto agile logs share the first entry's format but nothing
+/// after it, so they are rejected rather than misread.
+pub fn parse_bios_measurements(data: &[u8]) -> Result<Vec<PcrEvent>, ParseError> {
+    let mut reader = BlobReader::new(data);
+    let mut events = Vec::new();
+    while !reader.is_empty() {
+        let pcr_index = reader.read_u32_le()?;
+        let event_type = EventType::from_u32(reader.read_u32_le()?);
+        let digest = reader.read_digest()?;
+        let event_size = reader.read_u32_le()? as usize;
+        let event_offset = reader.offset();
+        let event = reader.read_bytes(event_size)?;
+        if events.is_empty() && event_type == EventType::NoAction && event.starts_with(SPEC_ID_EVENT03_SIGNATURE) {
+            return Err(ParseError::InvalidValue { offset: event_offset, field: "TCG_PCClientPCREventStruct.event" });
+        }
+        events.push(PcrEvent {
+            version_info: TSS_VERSION::default(),
+            pcr_index,
+            event_type,
+            pcr_value: digest.to_vec(),
+            event: event.to_vec()
+        });
+    }
+    Ok(events)
+}
+
+/// The payload of an `EV_EFI_VARIABLE_*` event, a `UEFI_VARIABLE_DATA`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EfiVariableData {
+    pub variable_name: [u8; 16],
+    pub unicode_name: String,
+    pub variable_data: Vec<u8>
+}
+
+/// Decoded event payloads for the event types that have a well-known
+/// structure. Anything else is left as `Raw`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventData {
+    SCrtmVersion(String),
+    EfiVariable(EfiVariableData),
+    Ipl(String),
+    /// A separator holds 0 normally, or 1 or 0xFFFFFFFF if the firmware hit
+    /// an error before handing over.
+    Separator(u32),
+    Raw(Vec<u8>)
+}
+
+/// Decodes the payload of `event` according to its event type.
+pub fn decode_event_data(event: &PcrEvent) -> Result<EventData, ParseError> {
+    let mut reader = BlobReader::new(&event.event);
+    match event.event_type {
+        EventType::SCrtmVersion => Ok(EventData::SCrtmVersion(decode_crtm_version(&event.event))),
+        EventType::EfiVariableDriverConfig | EventType::EfiVariableBoot | EventType::EfiVariableAuthority => {
+            let mut variable_name = [0; 16];
+            variable_name.copy_from_slice(reader.read_bytes(16)?);
+            let name_length = reader.read_u64_le()?;
+            let data_length = reader.read_u64_le()?;
+            if name_length > reader.remaining() as u64 / 2 {
+                return Err(reader.invalid("UEFI_VARIABLE_DATA.UnicodeNameLength"));
+            }
+            let unicode_name = decode_utf16_le(reader.read_bytes(name_length as usize * 2)?);
+            if data_length > reader.remaining() as u64 {
+                return Err(reader.invalid("UEFI_VARIABLE_DATA.VariableDataLength"));
+            }
+            let variable_data = reader.read_bytes(data_length as usize)?.to_vec();
+            Ok(EventData::EfiVariable(EfiVariableData { variable_name, unicode_name, variable_data }))
+        },
+        EventType::Ipl => {
+            let text = match event.event.iter().position(|byte| *byte == 0) {
+                Some(end) => &event.event[..end],
+                None => &event.event[..]
+            };
+            Ok(EventData::Ipl(String::from_utf8_lossy(text).into_owned()))
+        },
+        EventType::Separator => Ok(EventData::Separator(reader.read_u32_le()?)),
+        _ => Ok(EventData::Raw(event.event.clone()))
+    }
+}
+
+// Firmware usually records the CRTM version as a NUL-terminated UCS-2
+// string, but some record plain ASCII instead.
+fn decode_crtm_version(data: &[u8]) -> String {
+    let looks_utf16 = data.len() >= 2 && data.len().is_multiple_of(2) && data[1] == 0;
+    let text = if looks_utf16 {
+        decode_utf16_le(data)
+    } else {
+        String::from_utf8_lossy(data).into_owned()
+    };
+    text.trim_end_matches('\0').to_string()
+}
+
+fn decode_utf16_le(data: &[u8]) -> String {
+    let units: Vec<u16> = data.chunks(2)
+        .filter(|chunk| chunk.len() == 2)
+        .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use sha1::{Digest, Sha1};
+
+    use blob::ParseError;
+    use event_log::{EventType, PcrEvent};
+    use super::*;
+
+    // A TCG 1.2 log from a UEFI machine: CRTM version, a POST code, BootOrder,
+    // a GRUB command line and the PCR 0-7 separators
+    const TCG_1_2_LOG: &[u8] = include_bytes!("../testdata/binary_bios_measurements");
+    // The same machine with a TPM 2.0, starting with the Spec ID event
+    const CRYPTO_AGILE_LOG: &[u8] = include_bytes!("../testdata/binary_bios_measurements_crypto_agile");
+
+    #[test]
+    fn parses_tcg_1_2_log() {
+        let events = parse_bios_measurements(TCG_1_2_LOG).unwrap();
+        assert_eq!(events.len(), 12);
+        let types: Vec<EventType> = events.iter().take(4).map(|event| event.event_type).collect();
+        assert_eq!(types, vec![EventType::SCrtmVersion, EventType::PostCode, EventType::EfiVariableBoot, EventType::Ipl]);
+        assert_eq!(events[3].pcr_index, 4);
+        for event in &events {
+            assert_eq!(event.pcr_value[..], Sha1::digest(&event.event)[..]);
+        }
+        let separators: Vec<u32> = events[4..].iter()
+            .filter(|event| event.event_type == EventType::Separator)
+            .map(|event| event.pcr_index)
+            .collect();
+        assert_eq!(separators, (0..8).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn decodes_event_payloads() {
+        let events = parse_bios_measurements(TCG_1_2_LOG).unwrap();
+        assert_eq!(decode_event_data(&events[0]).unwrap(), EventData::SCrtmVersion("1.00".to_string()));
+        match decode_event_data(&events[2]).unwrap() {
+            EventData::EfiVariable(variable) => {
+                assert_eq!(variable.unicode_name, "BootOrder");
+                assert_eq!(variable.variable_data, vec![0, 0, 1, 0]);
+            },
+            other => panic!("unexpected payload {:?}", other)
+        }
+        assert_eq!(decode_event_data(&events[3]).unwrap(), EventData::Ipl("grub_cmd: linux /vmlinuz".to_string()));
+        assert_eq!(decode_event_data(&events[4]).unwrap(), EventData::Separator(0));
+        assert_eq!(decode_event_data(&events[1]).unwrap(), EventData::Raw(events[1].event.clone()));
+    }
+
+    #[test]
+    fn decodes_ascii_crtm_version() {
+        let event = PcrEvent::new(Default::default(), 0, EventType::SCrtmVersion, b"v2.1\0");
+        assert_eq!(decode_event_data(&event).unwrap(), EventData::SCrtmVersion("v2.1".to_string()));
+    }
+
+    #[test]
+    fn rejects_oversized_efi_variable_name() {
+        let mut event = parse_bios_measurements(TCG_1_2_LOG).unwrap().remove(2);
+        event.event[16] = 0xff;
+        assert_eq!(decode_event_data(&event), Err(ParseError::InvalidValue { offset: 32, field: "UEFI_VARIABLE_DATA.UnicodeNameLength" }));
+    }
+
+    #[test]
+    fn rejects_crypto_agile_log() {
+        assert_eq!(parse_bios_measurements(CRYPTO_AGILE_LOG).map(|events| events.len()), Err(ParseError::InvalidValue { offset: 32, field: "TCG_PCClientPCREventStruct.event" }));
+    }
+
+    #[test]
+    fn rejects_truncated_log() {
+        match parse_bios_measurements(&TCG_1_2_LOG[..TCG_1_2_LOG.len() - 1]) {
+            Err(ParseError::UnexpectedEnd { .. }) => {},
+            other => panic!("unexpected result {:?}", other.map(|events| events.len()))
+        }
+    }
+}
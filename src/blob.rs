@@ -0,0 +1,127 @@
+use std::error;
+use std::fmt;
+
+/// Error returned when a binary structure can't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input ended before the structure starting at `offset` did.
+    UnexpectedEnd { offset: usize },
+    /// The field `field` at `offset` holds a value the structure doesn't allow.
+    InvalidValue { offset: usize, field: &'static str }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnexpectedEnd { offset } =>
+                write!(fmt, "unexpected end of data at offset {}", offset),
+            ParseError::InvalidValue { offset, field } =>
+                write!(fmt, "invalid value for {} at offset {}", field, offset)
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// A `TPM_STRUCT_VER` or `TPM_VERSION`; the two share a layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TpmVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub rev_major: u8,
+    pub rev_minor: u8
+}
+
+/// Cursor over a byte buffer holding TPM structures.
+///
+/// TPM structures are big-endian; the `_le` readers are for firmware
+/// structures such as the PC Client event log.
+pub(crate) struct BlobReader<'a> {
+    data: &'a [u8],
+    offset: usize
+}
+
+impl<'a> BlobReader<'a> {
+    pub fn new(data: &'a [u8]) -> BlobReader<'a> {
+        BlobReader { data, offset: 0 }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn invalid(&self, field: &'static str) -> ParseError {
+        ParseError::InvalidValue { offset: self.offset, field }
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], ParseError> {
+        if length > self.remaining() {
+            return Err(ParseError::UnexpectedEnd { offset: self.offset });
+        }
+        let bytes = &self.data[self.offset..self.offset + length];
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, ParseError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, ParseError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, ParseError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    pub fn read_u32_le(&mut self) -> Result<u32, ParseError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_u64_le(&mut self) -> Result<u64, ParseError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_digest(&mut self) -> Result<[u8; 20], ParseError> {
+        let mut digest = [0; 20];
+        digest.copy_from_slice(self.read_bytes(20)?);
+        Ok(digest)
+    }
+
+    pub fn read_version(&mut self) -> Result<TpmVersion, ParseError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(TpmVersion { major: bytes[0], minor: bytes[1], rev_major: bytes[2], rev_minor: bytes[3] })
+    }
+
+    /// Reads a buffer preceded by its big-endian `UINT32` length.
+    pub fn read_sized_u32(&mut self) -> Result<&'a [u8], ParseError> {
+        let length = self.read_u32()? as usize;
+        self.read_bytes(length)
+    }
+
+    /// Reads a buffer preceded by its big-endian `UINT16` length.
+    pub fn read_sized_u16(&mut self) -> Result<&'a [u8], ParseError> {
+        let length = self.read_u16()? as usize;
+        self.read_bytes(length)
+    }
+}
@@ -0,0 +1,237 @@
+use std::fmt;
+
+use blob::{BlobReader, ParseError, TpmVersion};
+
+pub const TPM_TAG_CAP_VERSION_INFO: u16 = 0x0030;
+
+/// A `TPM_CAP_VERSION_INFO`, describing the TPM's specification level and
+/// vendor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapVersionInfo {
+    pub version: TpmVersion,
+    pub spec_level: u16,
+    pub errata_rev: u8,
+    pub tpm_vendor_id: [u8; 4],
+    pub vendor_specific: Vec<u8>
+}
+
+impl CapVersionInfo {
+    pub fn parse(data: &[u8]) -> Result<CapVersionInfo, ParseError> {
+        let mut reader = BlobReader::new(data);
+        let version_info = CapVersionInfo::read(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_CAP_VERSION_INFO"));
+        }
+        Ok(version_info)
+    }
+
+    pub(crate) fn read(reader: &mut BlobReader) -> Result<CapVersionInfo, ParseError> {
+        if reader.read_u16()? != TPM_TAG_CAP_VERSION_INFO {
+            return Err(reader.invalid("TPM_CAP_VERSION_INFO.tag"));
+        }
+        let version = reader.read_version()?;
+        let spec_level = reader.read_u16()?;
+        let errata_rev = reader.read_u8()?;
+        let mut tpm_vendor_id = [0; 4];
+        tpm_vendor_id.copy_from_slice(reader.read_bytes(4)?);
+        let vendor_specific = reader.read_sized_u16()?.to_vec();
+        Ok(CapVersionInfo { version, spec_level, errata_rev, tpm_vendor_id, vendor_specific })
+    }
+}
+
+/// Information about one layer of the TSS, the service provider (TSP) or
+/// the core services (TCS).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SoftwareStackInfo {
+    pub version: TpmVersion,
+    /// Whether the layer has a persistent key store.
+    pub persistent_storage: bool,
+    pub manufacturer_id: u32,
+    pub manufacturer: String
+}
+
+pub(crate) fn decode_cap_string(data: &[u8]) -> String {
+    let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// The TPM's `TPM_PERMANENT_FLAGS`, which persist across power cycles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PermanentFlags {
+    pub disable: bool,
+    pub ownership: bool,
+    pub deactivated: bool,
+    pub read_pubek: bool,
+    pub disable_owner_clear: bool,
+    pub allow_maintenance: bool,
+    pub physical_presence_lifetime_lock: bool,
+    pub physical_presence_hw_enable: bool,
+    pub physical_presence_cmd_enable: bool,
+    pub cekp_used: bool,
+    pub tpm_post: bool,
+    pub tpm_post_lock: bool,
+    pub fips: bool,
+    pub operator: bool,
+    pub enable_revoke_ek: bool,
+    pub nv_locked: bool,
+    pub read_srk_pub: bool,
+    pub tpm_established: bool,
+    pub maintenance_done: bool,
+    pub disable_full_da_logic_info: bool
+}
+
+impl PermanentFlags {
+    /// Decodes the non-volatile bitmap returned by `TPM_GetCapabilityOwner`, where
+    /// bit n holds the flag numbered n + 1 in the specification.
+    pub fn from_bitmap(bitmap: u32) -> PermanentFlags {
+        let flag = |n: u32| bitmap & (1 << (n - 1)) != 0;
+        PermanentFlags {
+            disable: flag(1),
+            ownership: flag(2),
+            deactivated: flag(3),
+            read_pubek: flag(4),
+            disable_owner_clear: flag(5),
+            allow_maintenance: flag(6),
+            physical_presence_lifetime_lock: flag(7),
+            physical_presence_hw_enable: flag(8),
+            physical_presence_cmd_enable: flag(9),
+            cekp_used: flag(10),
+            tpm_post: flag(11),
+            tpm_post_lock: flag(12),
+            fips: flag(13),
+            operator: flag(14),
+            enable_revoke_ek: flag(15),
+            nv_locked: flag(16),
+            read_srk_pub: flag(17),
+            tpm_established: flag(18),
+            maintenance_done: flag(19),
+            disable_full_da_logic_info: flag(20)
+        }
+    }
+}
+
+impl fmt::Display for PermanentFlags {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "disable: {}", self.disable)?;
+        writeln!(fmt, "ownership: {}", self.ownership)?;
+        writeln!(fmt, "deactivated: {}", self.deactivated)?;
+        writeln!(fmt, "readPubek: {}", self.read_pubek)?;
+        writeln!(fmt, "disableOwnerClear: {}", self.disable_owner_clear)?;
+        writeln!(fmt, "allowMaintenance: {}", self.allow_maintenance)?;
+        writeln!(fmt, "physicalPresenceLifetimeLock: {}", self.physical_presence_lifetime_lock)?;
+        writeln!(fmt, "physicalPresenceHWEnable: {}", self.physical_presence_hw_enable)?;
+        writeln!(fmt, "physicalPresenceCMDEnable: {}", self.physical_presence_cmd_enable)?;
+        writeln!(fmt, "CEKPUsed: {}", self.cekp_used)?;
+        writeln!(fmt, "TPMpost: {}", self.tpm_post)?;
+        writeln!(fmt, "TPMpostLock: {}", self.tpm_post_lock)?;
+        writeln!(fmt, "FIPS: {}", self.fips)?;
+        writeln!(fmt, "operator: {}", self.operator)?;
+        writeln!(fmt, "enableRevokeEK: {}", self.enable_revoke_ek)?;
+        writeln!(fmt, "nvLocked: {}", self.nv_locked)?;
+        writeln!(fmt, "readSRKPub: {}", self.read_srk_pub)?;
+        writeln!(fmt, "tpmEstablished: {}", self.tpm_established)?;
+        writeln!(fmt, "maintenanceDone: {}", self.maintenance_done)?;
+        writeln!(fmt, "disableFullDALogicInfo: {}", self.disable_full_da_logic_info)?;
+        Ok(())
+    }
+}
+
+/// The TPM's `TPM_STCLEAR_FLAGS`, which are reset by `TPM_Startup(ST_CLEAR)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StClearFlags {
+    pub deactivated: bool,
+    pub disable_force_clear: bool,
+    pub physical_presence: bool,
+    pub physical_presence_lock: bool,
+    pub global_lock: bool
+}
+
+impl StClearFlags {
+    /// Decodes the volatile bitmap returned by `TPM_GetCapabilityOwner`, where
+    /// bit n holds the flag numbered n + 1 in the specification.
+    pub fn from_bitmap(bitmap: u32) -> StClearFlags {
+        let flag = |n: u32| bitmap & (1 << (n - 1)) != 0;
+        StClearFlags {
+            deactivated: flag(1),
+            disable_force_clear: flag(2),
+            physical_presence: flag(3),
+            physical_presence_lock: flag(4),
+            global_lock: flag(5)
+        }
+    }
+}
+
+impl fmt::Display for StClearFlags {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "deactivated: {}", self.deactivated)?;
+        writeln!(fmt, "disableForceClear: {}", self.disable_force_clear)?;
+        writeln!(fmt, "physicalPresence: {}", self.physical_presence)?;
+        writeln!(fmt, "physicalPresenceLock: {}", self.physical_presence_lock)?;
+        writeln!(fmt, "bGlobalLock: {}", self.global_lock)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::hex;
+    use super::*;
+
+    // A 1.2 rev 116 TPM from vendor "IFX" with two bytes of vendor data
+    const VERSION_INFO: &str = "003001020074000200494658000002abcd";
+
+    #[test]
+    fn parses_version_info() {
+        let version_info = CapVersionInfo::parse(&hex(VERSION_INFO)).unwrap();
+        assert_eq!(version_info.version, TpmVersion { major: 1, minor: 2, rev_major: 0, rev_minor: 0x74 });
+        assert_eq!(version_info.spec_level, 2);
+        assert_eq!(version_info.errata_rev, 0);
+        assert_eq!(&version_info.tpm_vendor_id, b"IFX\0");
+        assert_eq!(version_info.vendor_specific, vec![0xab, 0xcd]);
+    }
+
+    #[test]
+    fn rejects_other_tag() {
+        let mut data = hex(VERSION_INFO);
+        data[1] = 0x36;
+        assert_eq!(CapVersionInfo::parse(&data), Err(ParseError::InvalidValue { offset: 2, field: "TPM_CAP_VERSION_INFO.tag" }));
+    }
+
+    #[test]
+    fn rejects_truncated_vendor_data() {
+        let data = hex(VERSION_INFO);
+        assert_eq!(CapVersionInfo::parse(&data[..data.len() - 1]).map(|_| ()), Err(ParseError::UnexpectedEnd { offset: 15 }));
+    }
+
+    #[test]
+    fn decodes_single_permanent_flags() {
+        assert_eq!(PermanentFlags::from_bitmap(0), PermanentFlags::default());
+        assert_eq!(PermanentFlags::from_bitmap(1 << 0), PermanentFlags { disable: true, ..Default::default() });
+        assert_eq!(PermanentFlags::from_bitmap(1 << 1), PermanentFlags { ownership: true, ..Default::default() });
+        assert_eq!(PermanentFlags::from_bitmap(1 << 8), PermanentFlags { physical_presence_cmd_enable: true, ..Default::default() });
+        assert_eq!(PermanentFlags::from_bitmap(1 << 15), PermanentFlags { nv_locked: true, ..Default::default() });
+        assert_eq!(PermanentFlags::from_bitmap(1 << 19), PermanentFlags { disable_full_da_logic_info: true, ..Default::default() });
+        assert_eq!(PermanentFlags::from_bitmap(1 << 20), PermanentFlags::default());
+    }
+
+    #[test]
+    fn decodes_single_stclear_flags() {
+        assert_eq!(StClearFlags::from_bitmap(0), StClearFlags::default());
+        assert_eq!(StClearFlags::from_bitmap(1 << 0), StClearFlags { deactivated: true, ..Default::default() });
+        assert_eq!(StClearFlags::from_bitmap(1 << 1), StClearFlags { disable_force_clear: true, ..Default::default() });
+        assert_eq!(StClearFlags::from_bitmap(1 << 2), StClearFlags { physical_presence: true, ..Default::default() });
+        assert_eq!(StClearFlags::from_bitmap(1 << 3), StClearFlags { physical_presence_lock: true, ..Default::default() });
+        assert_eq!(StClearFlags::from_bitmap(1 << 4), StClearFlags { global_lock: true, ..Default::default() });
+        assert_eq!(StClearFlags::from_bitmap(1 << 5), StClearFlags::default());
+    }
+
+    #[test]
+    fn displays_flags() {
+        let permanent = PermanentFlags::from_bitmap(1 << 1).to_string();
+        assert_eq!(permanent.lines().count(), 20);
+        assert!(permanent.starts_with("disable: false\nownership: true\n"));
+        assert!(permanent.ends_with("disableFullDALogicInfo: false\n"));
+        let stclear = StClearFlags::from_bitmap(1 << 4).to_string();
+        assert_eq!(stclear, "deactivated: false\ndisableForceClear: false\nphysicalPresence: false\nphysicalPresenceLock: false\nbGlobalLock: true\n");
+    }
+}
@@ -0,0 +1,314 @@
+use sha1::{Digest, Sha1};
+
+use blob::{BlobReader, ParseError, TpmVersion};
+use identity::KeyParms;
+use pubkey::RsaPublicKey;
+use quote::{PcrInfo, PcrInfoShort};
+use verify::VerifyError;
+use TssValidation;
+
+pub const TPM_TAG_CERTIFY_INFO2: u16 = 0x0029;
+
+pub const TPM_KEY_SIGNING: u16 = 0x0010;
+pub const TPM_KEY_STORAGE: u16 = 0x0011;
+pub const TPM_KEY_IDENTITY: u16 = 0x0012;
+pub const TPM_KEY_AUTHCHANGE: u16 = 0x0013;
+pub const TPM_KEY_BIND: u16 = 0x0014;
+pub const TPM_KEY_LEGACY: u16 = 0x0015;
+pub const TPM_KEY_MIGRATE: u16 = 0x0016;
+
+pub const TPM_KEY_FLAG_REDIRECTION: u32 = 0x00000001;
+pub const TPM_KEY_FLAG_MIGRATABLE: u32 = 0x00000002;
+pub const TPM_KEY_FLAG_IS_VOLATILE: u32 = 0x00000004;
+pub const TPM_KEY_FLAG_PCR_IGNORED_ON_READ: u32 = 0x00000008;
+pub const TPM_KEY_FLAG_MIGRATE_AUTHORITY: u32 = 0x00000010;
+
+pub const TPM_AUTH_NEVER: u8 = 0x00;
+pub const TPM_AUTH_ALWAYS: u8 = 0x01;
+pub const TPM_AUTH_PRIV_USE_ONLY: u8 = 0x03;
+
+/// The PCR binding of a certified key, in whichever form the TPM reported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CertifyPcrInfo {
+    /// From a `TPM_CERTIFY_INFO`.
+    Info(PcrInfo),
+    /// From a `TPM_CERTIFY_INFO2`.
+    Short(PcrInfoShort)
+}
+
+/// A `TPM_CERTIFY_INFO` or `TPM_CERTIFY_INFO2`, the structure signed by
+/// `TPM_CertifyKey`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CertifyInfo {
+    /// The `TPM_STRUCT_VER` of a `TPM_CERTIFY_INFO`; `None` for a
+    /// `TPM_CERTIFY_INFO2`.
+    pub version: Option<TpmVersion>,
+    /// Only present in a `TPM_CERTIFY_INFO2`.
+    pub payload_type: Option<u8>,
+    /// One of the `TPM_KEY_*` usages.
+    pub key_usage: u16,
+    /// `TPM_KEY_FLAG_*` bits.
+    pub key_flags: u32,
+    /// One of the `TPM_AUTH_*` values.
+    pub auth_data_usage: u8,
+    pub algorithm_parms: KeyParms,
+    /// SHA-1 of the certified key's modulus.
+    pub pubkey_digest: [u8; 20],
+    /// The anti-replay nonce.
+    pub data: [u8; 20],
+    /// Whether the parent key is bound to PCRs.
+    pub parent_pcr_status: bool,
+    /// `None` if the key isn't bound to PCRs.
+    pub pcr_info: Option<CertifyPcrInfo>,
+    /// The migration authority digest, only present in a
+    /// `TPM_CERTIFY_INFO2` and empty unless the key is a certifiable
+    /// migration key.
+    pub migration_authority: Option<Vec<u8>>
+}
+
+impl CertifyInfo {
+    pub fn parse(data: &[u8]) -> Result<CertifyInfo, ParseError> {
+        let mut reader = BlobReader::new(data);
+        let info2 = data.len() >= 2 && data[..2] == TPM_TAG_CERTIFY_INFO2.to_be_bytes();
+        let (version, payload_type) = if info2 {
+            reader.read_u16()?;
+            if reader.read_u8()? != 0 {
+                return Err(reader.invalid("TPM_CERTIFY_INFO2.fill"));
+            }
+            (None, Some(reader.read_u8()?))
+        } else {
+            (Some(reader.read_version()?), None)
+        };
+        let key_usage = reader.read_u16()?;
+        let key_flags = reader.read_u32()?;
+        let auth_data_usage = reader.read_u8()?;
+        let algorithm_parms = KeyParms::read(&mut reader)?;
+        let pubkey_digest = reader.read_digest()?;
+        let nonce = reader.read_digest()?;
+        let parent_pcr_status = reader.read_u8()? != 0;
+        let pcr_info_blob = reader.read_sized_u32()?;
+        let pcr_info = if pcr_info_blob.is_empty() {
+            None
+        } else {
+            let mut pcr_info_reader = BlobReader::new(pcr_info_blob);
+            let pcr_info = if info2 {
+                CertifyPcrInfo::Short(PcrInfoShort::read(&mut pcr_info_reader)?)
+            } else {
+                CertifyPcrInfo::Info(PcrInfo::read(&mut pcr_info_reader)?)
+            };
+            if !pcr_info_reader.is_empty() {
+                return Err(pcr_info_reader.invalid("TPM_CERTIFY_INFO.PCRInfo"));
+            }
+            Some(pcr_info)
+        };
+        let migration_authority = if info2 {
+            Some(reader.read_sized_u32()?.to_vec())
+        } else {
+            None
+        };
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_CERTIFY_INFO"));
+        }
+        Ok(CertifyInfo {
+            version,
+            payload_type,
+            key_usage,
+            key_flags,
+            auth_data_usage,
+            algorithm_parms,
+            pubkey_digest,
+            data: nonce,
+            parent_pcr_status,
+            pcr_info,
+            migration_authority
+        })
+    }
+}
+
+/// A signed `TPM_CERTIFY_INFO` or `TPM_CERTIFY_INFO2`, verifiable without
+/// a TPM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CertifiedKey {
+    pub certify_info: CertifyInfo,
+    /// The encoded structure the signature covers.
+    pub data: Vec<u8>,
+    pub signature: Vec<u8>
+}
+
+impl CertifiedKey {
+    pub fn new(data: &[u8], signature: &[u8]) -> Result<CertifiedKey, ParseError> {
+        Ok(CertifiedKey {
+            certify_info: CertifyInfo::parse(data)?,
+            data: data.to_vec(),
+            signature: signature.to_vec()
+        })
+    }
+
+    pub fn from_validation(validation: &TssValidation) -> Result<CertifiedKey, ParseError> {
+        CertifiedKey::new(&validation.data, &validation.validation_data)
+    }
+
+    /// Checks that `certifier_public_key` signed the certificate for
+    /// `expected_nonce`, and that it describes `key_public_key`: its
+    /// modulus, key length and exponent.
+    ///
+    /// The key's usage, flags and PCR binding are not checked; see
+    /// `certify_info`.
+    pub fn verify(&self, certifier_public_key: &RsaPublicKey, expected_nonce: &[u8; 20], key_public_key: &RsaPublicKey) -> Result<(), VerifyError> {
+        certifier_public_key.verify_sha1(&self.data, &self.signature)?;
+        if self.certify_info.data != *expected_nonce {
+            return Err(VerifyError::Mismatch("TPM_CERTIFY_INFO.data"));
+        }
+        if self.certify_info.pubkey_digest[..] != Sha1::digest(&key_public_key.modulus)[..] {
+            return Err(VerifyError::Mismatch("TPM_CERTIFY_INFO.pubkeyDigest"));
+        }
+        let key_parms = self.certify_info.algorithm_parms.rsa_key_parms()?;
+        if key_parms.key_length != key_public_key.key_parms.key_length {
+            return Err(VerifyError::Mismatch("TPM_RSA_KEY_PARMS.keyLength"));
+        }
+        if key_parms.exponent() != key_public_key.exponent() {
+            return Err(VerifyError::Mismatch("TPM_RSA_KEY_PARMS.exponent"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::{key_a, key_b, sign_sha1, signing_public_key};
+    use super::*;
+
+    const NONCE: [u8; 20] = [0x3c; 20];
+
+    // A TPM_CERTIFY_INFO for a signing key with the given PCR binding
+    fn certify_info(key: &RsaPublicKey, nonce: &[u8; 20], pcr_info: &[u8]) -> Vec<u8> {
+        let mut data = vec![1, 1, 0, 0];
+        data.extend_from_slice(&TPM_KEY_SIGNING.to_be_bytes());
+        data.extend_from_slice(&TPM_KEY_FLAG_MIGRATABLE.to_be_bytes());
+        data.push(TPM_AUTH_ALWAYS);
+        key.key_parms.write(&mut data);
+        data.extend_from_slice(&Sha1::digest(&key.modulus));
+        data.extend_from_slice(nonce);
+        data.push(0);
+        data.extend_from_slice(&(pcr_info.len() as u32).to_be_bytes());
+        data.extend_from_slice(pcr_info);
+        data
+    }
+
+    fn certified_key(key: &RsaPublicKey) -> CertifiedKey {
+        let data = certify_info(key, &NONCE, &[]);
+        CertifiedKey::new(&data, &sign_sha1(&key_a(), &data)).unwrap()
+    }
+
+    #[test]
+    fn parses_certify_info() {
+        let key = signing_public_key(&key_b());
+        let certify_info = CertifyInfo::parse(&certify_info(&key, &NONCE, &[])).unwrap();
+        assert_eq!(certify_info.version, Some(TpmVersion { major: 1, minor: 1, rev_major: 0, rev_minor: 0 }));
+        assert_eq!(certify_info.payload_type, None);
+        assert_eq!(certify_info.key_usage, TPM_KEY_SIGNING);
+        assert_eq!(certify_info.key_flags, TPM_KEY_FLAG_MIGRATABLE);
+        assert_eq!(certify_info.auth_data_usage, TPM_AUTH_ALWAYS);
+        assert_eq!(certify_info.algorithm_parms.rsa_key_parms(), Ok(key.key_parms));
+        assert_eq!(certify_info.data, NONCE);
+        assert!(!certify_info.parent_pcr_status);
+        assert_eq!(certify_info.pcr_info, None);
+        assert_eq!(certify_info.migration_authority, None);
+    }
+
+    #[test]
+    fn parses_certify_info_pcr_binding() {
+        let mut pcr_info = vec![0, 3, 0x80, 0, 0];
+        pcr_info.extend_from_slice(&[0x11; 20]);
+        pcr_info.extend_from_slice(&[0x22; 20]);
+        let certify_info = CertifyInfo::parse(&certify_info(&signing_public_key(&key_b()), &NONCE, &pcr_info)).unwrap();
+        match certify_info.pcr_info {
+            Some(CertifyPcrInfo::Info(pcr_info)) => {
+                assert_eq!(pcr_info.pcr_selection.pcr_indices(), vec![7]);
+                assert_eq!(pcr_info.digest_at_release, [0x11; 20]);
+                assert_eq!(pcr_info.digest_at_creation, [0x22; 20]);
+            },
+            other => panic!("unexpected PCR info {:?}", other)
+        }
+    }
+
+    #[test]
+    fn parses_certify_info2() {
+        let mut pcr_info = vec![0, 3, 0x80, 0, 0, 0x01];
+        pcr_info.extend_from_slice(&[0x11; 20]);
+        let mut data = TPM_TAG_CERTIFY_INFO2.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0, 1]);
+        data.extend_from_slice(&certify_info(&signing_public_key(&key_b()), &NONCE, &pcr_info)[4..]);
+        data.extend_from_slice(&[0, 0, 0, 20]);
+        data.extend_from_slice(&[0x33; 20]);
+        let certify_info = CertifyInfo::parse(&data).unwrap();
+        assert_eq!(certify_info.version, None);
+        assert_eq!(certify_info.payload_type, Some(1));
+        match certify_info.pcr_info {
+            Some(CertifyPcrInfo::Short(ref pcr_info)) => assert_eq!(pcr_info.locality_at_release, 0x01),
+            ref other => panic!("unexpected PCR info {:?}", other)
+        }
+        assert_eq!(certify_info.migration_authority, Some(vec![0x33; 20]));
+    }
+
+    #[test]
+    fn rejects_certify_info2_fill() {
+        let mut data = TPM_TAG_CERTIFY_INFO2.to_be_bytes().to_vec();
+        data.extend_from_slice(&[1, 1]);
+        assert_eq!(CertifyInfo::parse(&data), Err(ParseError::InvalidValue { offset: 3, field: "TPM_CERTIFY_INFO2.fill" }));
+    }
+
+    #[test]
+    fn rejects_trailing_pcr_info() {
+        let pcr_info = vec![0; 46];
+        assert_eq!(CertifyInfo::parse(&certify_info(&signing_public_key(&key_b()), &NONCE, &pcr_info)).map(|_| ()), Err(ParseError::InvalidValue { offset: 42, field: "TPM_CERTIFY_INFO.PCRInfo" }));
+    }
+
+    #[test]
+    fn verifies_certified_key() {
+        let key = signing_public_key(&key_b());
+        assert_eq!(certified_key(&key).verify(&signing_public_key(&key_a()), &NONCE, &key), Ok(()));
+    }
+
+    #[test]
+    fn rejects_other_certifier() {
+        let key = signing_public_key(&key_b());
+        assert_eq!(certified_key(&key).verify(&signing_public_key(&key_b()), &NONCE, &key), Err(VerifyError::BadSignature));
+    }
+
+    #[test]
+    fn rejects_other_nonce() {
+        let key = signing_public_key(&key_b());
+        assert_eq!(certified_key(&key).verify(&signing_public_key(&key_a()), &[0; 20], &key), Err(VerifyError::Mismatch("TPM_CERTIFY_INFO.data")));
+    }
+
+    #[test]
+    fn rejects_other_key() {
+        let key = signing_public_key(&key_b());
+        assert_eq!(certified_key(&key).verify(&signing_public_key(&key_a()), &NONCE, &signing_public_key(&key_a())), Err(VerifyError::Mismatch("TPM_CERTIFY_INFO.pubkeyDigest")));
+    }
+
+    #[test]
+    fn rejects_other_key_length() {
+        let key = signing_public_key(&key_b());
+        let mut certified = key.clone();
+        certified.key_parms.key_length = 2048;
+        assert_eq!(certified_key(&certified).verify(&signing_public_key(&key_a()), &NONCE, &key), Err(VerifyError::Mismatch("TPM_RSA_KEY_PARMS.keyLength")));
+    }
+
+    #[test]
+    fn rejects_other_exponent() {
+        let key = signing_public_key(&key_b());
+        let mut certified = key.clone();
+        certified.key_parms.exponent = vec![0x03];
+        assert_eq!(certified_key(&certified).verify(&signing_public_key(&key_a()), &NONCE, &key), Err(VerifyError::Mismatch("TPM_RSA_KEY_PARMS.exponent")));
+    }
+
+    #[test]
+    fn accepts_explicit_default_exponent() {
+        let key = signing_public_key(&key_b());
+        let mut certified = key.clone();
+        certified.key_parms.exponent = vec![0x01, 0x00, 0x01];
+        assert_eq!(certified_key(&certified).verify(&signing_public_key(&key_a()), &NONCE, &key), Ok(()));
+    }
+}
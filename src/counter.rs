@@ -0,0 +1,246 @@
+use std::cmp;
+use std::error;
+use std::fmt;
+
+use trousers_sys::tspi::TPM_COUNTER_VALUE;
+
+use blob::{BlobReader, ParseError};
+use {TssError, TssNvStore};
+
+pub const TPM_TAG_COUNTER_VALUE: u16 = 0x000e;
+
+/// Value of `TSS_TPMCAP_PROP_ACTIVECOUNTER` when no counter is active.
+pub const TPM_COUNT_ID_NULL: u32 = 0xffffffff;
+
+/// Length of an `NvSequenceCounter` in NV.
+pub const NV_SEQUENCE_COUNTER_LENGTH: usize = 8;
+
+// Erased NV reads as all ones, so that value is reserved for "never written".
+const NV_COUNTER_BLANK: u64 = u64::MAX;
+
+/// A `TPM_COUNTER_VALUE`, as reported with audit digests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CounterValue {
+    pub label: [u8; 4],
+    pub counter: u32
+}
+
+impl CounterValue {
+    pub fn parse(blob: &[u8]) -> Result<CounterValue, ParseError> {
+        let mut reader = BlobReader::new(blob);
+        if reader.read_u16()? != TPM_TAG_COUNTER_VALUE {
+            return Err(reader.invalid("TPM_COUNTER_VALUE.tag"));
+        }
+        let mut label = [0; 4];
+        label.copy_from_slice(reader.read_bytes(4)?);
+        let counter = reader.read_u32()?;
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_COUNTER_VALUE"));
+        }
+        Ok(CounterValue { label, counter })
+    }
+}
+
+impl From<TPM_COUNTER_VALUE> for CounterValue {
+    fn from(value: TPM_COUNTER_VALUE) -> CounterValue {
+        CounterValue { label: value.label, counter: value.counter }
+    }
+}
+
+/// Error returned by `NvSequenceCounter`.
+#[derive(Debug)]
+pub enum CounterError {
+    Tss(TssError),
+    /// The requested value is below the counter's current value.
+    WouldDecrease { current: u64, requested: u64 },
+    /// The counter can't go any higher until the TPM's counter advances.
+    Overflow,
+    /// NV holds a value from a later TPM counter value than the TPM's
+    /// current one, so it wasn't written against this counter.
+    AheadOfTpm { epoch: u32, tpm_counter: u32 }
+}
+
+impl fmt::Display for CounterError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CounterError::Tss(ref error) => write!(fmt, "NV access failed: {}", error),
+            CounterError::WouldDecrease { current, requested } =>
+                write!(fmt, "counter is at {} and cannot go back to {}", current, requested),
+            CounterError::Overflow => fmt.write_str("counter is at its maximum"),
+            CounterError::AheadOfTpm { epoch, tpm_counter } =>
+                write!(fmt, "counter was written at TPM counter value {} but the TPM counter is at {}", epoch, tpm_counter)
+        }
+    }
+}
+
+impl error::Error for CounterError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CounterError::Tss(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<TssError> for CounterError {
+    fn from(error: TssError) -> CounterError {
+        CounterError::Tss(error)
+    }
+}
+
+/// A 64-bit counter kept in NV and bound to the TPM's active monotonic
+/// counter.
+///
+/// The upper 32 bits of the value are the TPM counter's value when it was
+/// last written, and every read checks them against
+/// `TssTPM::read_counter`. Once the TPM counter has advanced, older values
+/// read as `tpm_counter << 32`, so the counter never reads lower than the
+/// TPM counter allows. Within one TPM counter value the TPM enforces
+/// nothing: anyone who can write the area can write a lower value. TSS 1.2
+/// can't increment the TPM's counters, so advance the TPM counter
+/// elsewhere (for instance with `TPM_IncrementCounter` at each release) to
+/// retire old values for good.
+///
+/// Reading needs an active TPM counter. Define the area with `AUTH_WRITE`
+/// or `OWNER_WRITE` so that only holders of that secret can write it. Each
+/// update is a single 8 byte `TPM_NV_WriteValue` and can't be torn. An
+/// area that has never been written reads as `tpm_counter << 32`.
+pub struct NvSequenceCounter<'nv, 'context: 'nv> {
+    nv_store: &'nv TssNvStore<'context>,
+    offset: u32
+}
+
+impl<'nv, 'context> NvSequenceCounter<'nv, 'context> {
+    pub fn new(nv_store: &'nv TssNvStore<'context>, offset: u32) -> NvSequenceCounter<'nv, 'context> {
+        NvSequenceCounter { nv_store, offset }
+    }
+
+    pub fn read(&self) -> Result<u64, CounterError> {
+        Ok(self.read_with_tpm_counter()?.0)
+    }
+
+    /// Adds one to the counter, returning the new value.
+    pub fn increment(&self) -> Result<u64, CounterError> {
+        let (current, tpm_counter) = self.read_with_tpm_counter()?;
+        let value = current.checked_add(1).ok_or(CounterError::Overflow)?;
+        check_advance(current, value, tpm_counter)?;
+        self.write(value)?;
+        Ok(value)
+    }
+
+    /// Moves the counter up to `value`, for instance a firmware version
+    /// being installed. Fails if the counter is already past `value`, or if
+    /// `value` is beyond the TPM counter's current value.
+    pub fn advance_to(&self, value: u64) -> Result<(), CounterError> {
+        let (current, tpm_counter) = self.read_with_tpm_counter()?;
+        check_advance(current, value, tpm_counter)?;
+        if value > current {
+            self.write(value)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that `value` hasn't been superseded, for instance before
+    /// booting a firmware version.
+    pub fn check_at_least(&self, value: u64) -> Result<(), CounterError> {
+        let current = self.read()?;
+        if value < current {
+            return Err(CounterError::WouldDecrease { current, requested: value });
+        }
+        Ok(())
+    }
+
+    fn read_with_tpm_counter(&self) -> Result<(u64, u32), CounterError> {
+        let data = self.nv_store.read(self.offset, NV_SEQUENCE_COUNTER_LENGTH)?;
+        let mut bytes = [0; NV_SEQUENCE_COUNTER_LENGTH];
+        bytes.copy_from_slice(&data);
+        let tpm_counter = self.nv_store.context.get_tpm_object()?.read_counter()?;
+        Ok((current_value(u64::from_be_bytes(bytes), tpm_counter)?, tpm_counter))
+    }
+
+    fn write(&self, value: u64) -> Result<(), CounterError> {
+        Ok(self.nv_store.write_value(self.offset, &value.to_be_bytes())?)
+    }
+}
+
+fn epoch_floor(tpm_counter: u32) -> u64 {
+    (tpm_counter as u64) << 32
+}
+
+/// Returns the counter's value given what NV holds and the TPM counter.
+fn current_value(stored: u64, tpm_counter: u32) -> Result<u64, CounterError> {
+    if stored == NV_COUNTER_BLANK {
+        return Ok(epoch_floor(tpm_counter));
+    }
+    let epoch = (stored >> 32) as u32;
+    if epoch > tpm_counter {
+        return Err(CounterError::AheadOfTpm { epoch, tpm_counter });
+    }
+    Ok(cmp::max(stored, epoch_floor(tpm_counter)))
+}
+
+/// Checks that the counter may move from `current` to `requested` while
+/// the TPM counter reads `tpm_counter`.
+fn check_advance(current: u64, requested: u64, tpm_counter: u32) -> Result<(), CounterError> {
+    if requested < current {
+        return Err(CounterError::WouldDecrease { current, requested });
+    }
+    if requested >> 32 != tpm_counter as u64 || requested == NV_COUNTER_BLANK {
+        return Err(CounterError::Overflow);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use blob::ParseError;
+    use test_util::hex;
+    use super::*;
+
+    #[test]
+    fn parses_counter_value() {
+        assert_eq!(CounterValue::parse(&hex("000e6669726d0000002a")), Ok(CounterValue { label: *b"firm", counter: 42 }));
+        assert_eq!(CounterValue::parse(&hex("000f6669726d0000002a")), Err(ParseError::InvalidValue { offset: 2, field: "TPM_COUNTER_VALUE.tag" }));
+        assert_eq!(CounterValue::parse(&hex("000e6669726d000000")), Err(ParseError::UnexpectedEnd { offset: 6 }));
+    }
+
+    #[test]
+    fn reads_value_bound_to_tpm_counter() {
+        assert_eq!(current_value(NV_COUNTER_BLANK, 0).unwrap(), 0);
+        assert_eq!(current_value(NV_COUNTER_BLANK, 3).unwrap(), 3 << 32);
+        assert_eq!(current_value((3 << 32) + 7, 3).unwrap(), (3 << 32) + 7);
+        // Values from before the TPM counter advanced are retired
+        assert_eq!(current_value((2 << 32) + 7, 3).unwrap(), 3 << 32);
+        assert_eq!(current_value(7, 3).unwrap(), 3 << 32);
+    }
+
+    #[test]
+    fn rejects_value_ahead_of_tpm_counter() {
+        match current_value((4 << 32) + 1, 3) {
+            Err(CounterError::AheadOfTpm { epoch: 4, tpm_counter: 3 }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn advances_within_tpm_counter_value() {
+        assert!(check_advance(3 << 32, (3 << 32) + 1, 3).is_ok());
+        assert!(check_advance((3 << 32) + 5, (3 << 32) + 5, 3).is_ok());
+        match check_advance((3 << 32) + 5, (3 << 32) + 4, 3) {
+            Err(CounterError::WouldDecrease { current, requested }) if current == (3 << 32) + 5 && requested == (3 << 32) + 4 => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_advance_past_tpm_counter_value() {
+        match check_advance((3 << 32) + 0xffffffff, 4 << 32, 3) {
+            Err(CounterError::Overflow) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+        match check_advance(u64::MAX - 1, u64::MAX, u32::MAX) {
+            Err(CounterError::Overflow) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+use sha1::{Digest, Sha1};
+
+use memory::wipe;
+use pubkey::RsaPublicKey;
+use verify::VerifyError;
+use TssValidation;
+
+/// The reset authorization of a revocable endorsement key, needed to revoke
+/// it with `TssTPM::revoke_endorsement_key`.
+///
+/// The secret is wiped from memory when dropped and is left out of `Debug`
+/// output. Anyone holding it can remove the EK, so store it like an owner
+/// secret.
+pub struct EkResetSecret {
+    secret: [u8; 20]
+}
+
+impl EkResetSecret {
+    pub fn from_bytes(secret: [u8; 20]) -> EkResetSecret {
+        EkResetSecret { secret }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.secret
+    }
+}
+
+impl fmt::Debug for EkResetSecret {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("EkResetSecret(..)")
+    }
+}
+
+impl Drop for EkResetSecret {
+    fn drop(&mut self) {
+        wipe(&mut self.secret);
+    }
+}
+
+/// Checks the result of `TssTPM::get_pub_endorsement_key_with_nonce`: the
+/// TPM's checksum must be the SHA-1 of the returned `TPM_PUBKEY` followed by
+/// the anti-replay nonce. Returns the decoded endorsement key.
+pub fn verify_pub_endorsement_key(validation: &TssValidation, expected_nonce: &[u8; 20]) -> Result<RsaPublicKey, VerifyError> {
+    if validation.external_data[..] != expected_nonce[..] {
+        return Err(VerifyError::Mismatch("antiReplay"));
+    }
+    let mut hasher = Sha1::new();
+    hasher.update(&validation.data);
+    hasher.update(expected_nonce);
+    if hasher.finalize()[..] != validation.validation_data[..] {
+        return Err(VerifyError::Mismatch("checksum"));
+    }
+    Ok(RsaPublicKey::from_tpm_pubkey(&validation.data)?)
+}
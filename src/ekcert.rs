@@ -0,0 +1,144 @@
+use rsa::pkcs1::DecodeRsaPublicKey;
+use x509_cert::der::Decode;
+use x509_cert::Certificate;
+
+use blob::{BlobReader, ParseError};
+use pubkey::RsaPublicKey;
+use verify::VerifyError;
+
+/// NV index holding the endorsement key certificate.
+pub const TPM_NV_INDEX_EKCERT: u32 = 0x1000f000;
+
+pub const TCG_TAG_PCCLIENT_STORED_CERT: u16 = 0x1001;
+pub const TCG_TAG_PCCLIENT_FULL_CERT: u16 = 0x1002;
+
+pub const TCG_FULL_CERT: u8 = 0;
+pub const TCG_PARTIAL_SMALL_CERT: u8 = 1;
+
+/// Length of the `TCG_PCCLIENT_STORED_CERT` header before the certificate.
+pub const STORED_CERT_HEADER_LENGTH: usize = 5;
+
+/// Decodes a `TCG_PCCLIENT_STORED_CERT` header, returning how many bytes
+/// of NV follow it.
+pub fn stored_cert_size(header: &[u8]) -> Result<usize, ParseError> {
+    let mut reader = BlobReader::new(header);
+    if reader.read_u16()? != TCG_TAG_PCCLIENT_STORED_CERT {
+        return Err(reader.invalid("TCG_PCCLIENT_STORED_CERT.tag"));
+    }
+    if reader.read_u8()? != TCG_FULL_CERT {
+        // Partial certificates need the rest of the certificate from the
+        // manufacturer, so they are no use on their own.
+        return Err(reader.invalid("TCG_PCCLIENT_STORED_CERT.certType"));
+    }
+    Ok(reader.read_u16()? as usize)
+}
+
+/// Strips the `TCG_PCCLIENT_STORED_CERT` and `TCG_FULL_CERT` headers from
+/// the contents of `TPM_NV_INDEX_EKCERT`, returning the DER certificate.
+/// Anything after the stored certificate is ignored.
+pub fn parse_stored_cert(nv_data: &[u8]) -> Result<Vec<u8>, ParseError> {
+    let cert_size = stored_cert_size(nv_data)?;
+    let mut reader = BlobReader::new(nv_data);
+    reader.read_bytes(STORED_CERT_HEADER_LENGTH)?;
+    let mut full_cert = BlobReader::new(reader.read_bytes(cert_size)?);
+    if full_cert.read_u16()? != TCG_TAG_PCCLIENT_FULL_CERT {
+        return Err(full_cert.invalid("TCG_FULL_CERT.tag"));
+    }
+    let remaining = full_cert.remaining();
+    Ok(full_cert.read_bytes(remaining)?.to_vec())
+}
+
+/// Checks that the subject public key of the DER certificate `cert` is the
+/// endorsement key `ek`, as read by `TssTPM::get_pub_endorsement_key`.
+pub fn verify_ek_certificate_key(cert: &[u8], ek: &RsaPublicKey) -> Result<(), VerifyError> {
+    let cert = Certificate::from_der(cert).map_err(|_| VerifyError::InvalidCertificate)?;
+    let subject_public_key = cert.tbs_certificate.subject_public_key_info.subject_public_key;
+    let subject_public_key = subject_public_key.as_bytes().ok_or(VerifyError::InvalidCertificate)?;
+    let cert_key = rsa::RsaPublicKey::from_pkcs1_der(subject_public_key).map_err(|_| VerifyError::InvalidCertificate)?;
+    if cert_key != ek.to_rsa()? {
+        return Err(VerifyError::Mismatch("subjectPublicKey"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pubkey::{TPM_ES_RSAESOAEP_SHA1_MGF1, TPM_SS_NONE};
+    use test_util::{key_a, key_c};
+    use super::*;
+
+    // A self-signed certificate for test key C
+    const EK_CERT: &[u8] = include_bytes!("../testdata/test_ek_cert.der");
+
+    // The contents of TPM_NV_INDEX_EKCERT holding `cert`, padded with
+    // erased NV as the index is usually larger than the certificate
+    fn nv_data(cert: &[u8]) -> Vec<u8> {
+        let mut data = TCG_TAG_PCCLIENT_STORED_CERT.to_be_bytes().to_vec();
+        data.push(TCG_FULL_CERT);
+        data.extend_from_slice(&(cert.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(&TCG_TAG_PCCLIENT_FULL_CERT.to_be_bytes());
+        data.extend_from_slice(cert);
+        data.extend_from_slice(&[0xff; 16]);
+        data
+    }
+
+    fn ek(key: &rsa::RsaPrivateKey) -> RsaPublicKey {
+        RsaPublicKey::from_rsa(&key.to_public_key(), TPM_ES_RSAESOAEP_SHA1_MGF1, TPM_SS_NONE)
+    }
+
+    #[test]
+    fn strips_nv_headers() {
+        assert_eq!(parse_stored_cert(&nv_data(EK_CERT)), Ok(EK_CERT.to_vec()));
+    }
+
+    #[test]
+    fn reads_header_then_certificate() {
+        // As TssTPM::get_ek_certificate reads it, in two NV reads
+        let data = nv_data(EK_CERT);
+        let cert_size = stored_cert_size(&data[..STORED_CERT_HEADER_LENGTH]).unwrap();
+        assert_eq!(cert_size, EK_CERT.len() + 2);
+        let mut read = data[..STORED_CERT_HEADER_LENGTH].to_vec();
+        read.extend_from_slice(&data[STORED_CERT_HEADER_LENGTH..STORED_CERT_HEADER_LENGTH + cert_size]);
+        assert_eq!(parse_stored_cert(&read), Ok(EK_CERT.to_vec()));
+    }
+
+    #[test]
+    fn rejects_other_stored_cert_tag() {
+        let mut data = nv_data(EK_CERT);
+        data[1] = 0x02;
+        assert_eq!(parse_stored_cert(&data), Err(ParseError::InvalidValue { offset: 2, field: "TCG_PCCLIENT_STORED_CERT.tag" }));
+    }
+
+    #[test]
+    fn rejects_partial_cert() {
+        let mut data = nv_data(EK_CERT);
+        data[2] = TCG_PARTIAL_SMALL_CERT;
+        assert_eq!(parse_stored_cert(&data), Err(ParseError::InvalidValue { offset: 3, field: "TCG_PCCLIENT_STORED_CERT.certType" }));
+    }
+
+    #[test]
+    fn rejects_other_full_cert_tag() {
+        let mut data = nv_data(EK_CERT);
+        data[6] = 0x01;
+        assert_eq!(parse_stored_cert(&data), Err(ParseError::InvalidValue { offset: 2, field: "TCG_FULL_CERT.tag" }));
+    }
+
+    #[test]
+    fn rejects_oversized_length() {
+        let mut data = nv_data(EK_CERT);
+        data[3..5].copy_from_slice(&(EK_CERT.len() as u16 + 100).to_be_bytes());
+        assert_eq!(parse_stored_cert(&data), Err(ParseError::UnexpectedEnd { offset: STORED_CERT_HEADER_LENGTH }));
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        assert_eq!(stored_cert_size(&[0x10, 0x01, 0x00, 0x02]), Err(ParseError::UnexpectedEnd { offset: 3 }));
+    }
+
+    #[test]
+    fn checks_certificate_key() {
+        assert_eq!(verify_ek_certificate_key(EK_CERT, &ek(&key_c())), Ok(()));
+        assert_eq!(verify_ek_certificate_key(EK_CERT, &ek(&key_a())), Err(VerifyError::Mismatch("subjectPublicKey")));
+        assert_eq!(verify_ek_certificate_key(&EK_CERT[1..], &ek(&key_c())), Err(VerifyError::InvalidCertificate));
+    }
+}
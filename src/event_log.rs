@@ -0,0 +1,186 @@
+use libc;
+use trousers_sys::tspi::*;
+
+use super::copy_raw_ptr_to_vec;
+
+/// Event types from the TCG PC Client specification, as recorded in the
+/// `eventType` field of a `TSS_PCR_EVENT`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventType {
+    PrebootCert,
+    PostCode,
+    Unused,
+    NoAction,
+    Separator,
+    Action,
+    EventTag,
+    SCrtmContents,
+    SCrtmVersion,
+    CpuMicrocode,
+    PlatformConfigFlags,
+    TableOfDevices,
+    CompactHash,
+    Ipl,
+    IplPartitionData,
+    NonhostCode,
+    NonhostConfig,
+    NonhostInfo,
+    OmitBootDeviceEvents,
+    EfiVariableDriverConfig,
+    EfiVariableBoot,
+    EfiBootServicesApplication,
+    EfiBootServicesDriver,
+    EfiRuntimeServicesDriver,
+    EfiGptEvent,
+    EfiAction,
+    EfiPlatformFirmwareBlob,
+    EfiHandoffTables,
+    EfiVariableAuthority,
+    Unknown(u32)
+}
+
+pub const EV_PREBOOT_CERT: u32 = 0x00000000;
+pub const EV_POST_CODE: u32 = 0x00000001;
+pub const EV_UNUSED: u32 = 0x00000002;
+pub const EV_NO_ACTION: u32 = 0x00000003;
+pub const EV_SEPARATOR: u32 = 0x00000004;
+pub const EV_ACTION: u32 = 0x00000005;
+pub const EV_EVENT_TAG: u32 = 0x00000006;
+pub const EV_S_CRTM_CONTENTS: u32 = 0x00000007;
+pub const EV_S_CRTM_VERSION: u32 = 0x00000008;
+pub const EV_CPU_MICROCODE: u32 = 0x00000009;
+pub const EV_PLATFORM_CONFIG_FLAGS: u32 = 0x0000000a;
+pub const EV_TABLE_OF_DEVICES: u32 = 0x0000000b;
+pub const EV_COMPACT_HASH: u32 = 0x0000000c;
+pub const EV_IPL: u32 = 0x0000000d;
+pub const EV_IPL_PARTITION_DATA: u32 = 0x0000000e;
+pub const EV_NONHOST_CODE: u32 = 0x0000000f;
+pub const EV_NONHOST_CONFIG: u32 = 0x00000010;
+pub const EV_NONHOST_INFO: u32 = 0x00000011;
+pub const EV_OMIT_BOOT_DEVICE_EVENTS: u32 = 0x00000012;
+pub const EV_EFI_VARIABLE_DRIVER_CONFIG: u32 = 0x80000001;
+pub const EV_EFI_VARIABLE_BOOT: u32 = 0x80000002;
+pub const EV_EFI_BOOT_SERVICES_APPLICATION: u32 = 0x80000003;
+pub const EV_EFI_BOOT_SERVICES_DRIVER: u32 = 0x80000004;
+pub const EV_EFI_RUNTIME_SERVICES_DRIVER: u32 = 0x80000005;
+pub const EV_EFI_GPT_EVENT: u32 = 0x80000006;
+pub const EV_EFI_ACTION: u32 = 0x80000007;
+pub const EV_EFI_PLATFORM_FIRMWARE_BLOB: u32 = 0x80000008;
+pub const EV_EFI_HANDOFF_TABLES: u32 = 0x80000009;
+pub const EV_EFI_VARIABLE_AUTHORITY: u32 = 0x800000e0;
+
+impl EventType {
+    pub fn from_u32(event_type: u32) -> EventType {
+        match event_type {
+            EV_PREBOOT_CERT => EventType::PrebootCert,
+            EV_POST_CODE => EventType::PostCode,
+            EV_UNUSED => EventType::Unused,
+            EV_NO_ACTION => EventType::NoAction,
+            EV_SEPARATOR => EventType::Separator,
+            EV_ACTION => EventType::Action,
+            EV_EVENT_TAG => EventType::EventTag,
+            EV_S_CRTM_CONTENTS => EventType::SCrtmContents,
+            EV_S_CRTM_VERSION => EventType::SCrtmVersion,
+            EV_CPU_MICROCODE => EventType::CpuMicrocode,
+            EV_PLATFORM_CONFIG_FLAGS => EventType::PlatformConfigFlags,
+            EV_TABLE_OF_DEVICES => EventType::TableOfDevices,
+            EV_COMPACT_HASH => EventType::CompactHash,
+            EV_IPL => EventType::Ipl,
+            EV_IPL_PARTITION_DATA => EventType::IplPartitionData,
+            EV_NONHOST_CODE => EventType::NonhostCode,
+            EV_NONHOST_CONFIG => EventType::NonhostConfig,
+            EV_NONHOST_INFO => EventType::NonhostInfo,
+            EV_OMIT_BOOT_DEVICE_EVENTS => EventType::OmitBootDeviceEvents,
+            EV_EFI_VARIABLE_DRIVER_CONFIG => EventType::EfiVariableDriverConfig,
+            EV_EFI_VARIABLE_BOOT => EventType::EfiVariableBoot,
+            EV_EFI_BOOT_SERVICES_APPLICATION => EventType::EfiBootServicesApplication,
+            EV_EFI_BOOT_SERVICES_DRIVER => EventType::EfiBootServicesDriver,
+            EV_EFI_RUNTIME_SERVICES_DRIVER => EventType::EfiRuntimeServicesDriver,
+            EV_EFI_GPT_EVENT => EventType::EfiGptEvent,
+            EV_EFI_ACTION => EventType::EfiAction,
+            EV_EFI_PLATFORM_FIRMWARE_BLOB => EventType::EfiPlatformFirmwareBlob,
+            EV_EFI_HANDOFF_TABLES => EventType::EfiHandoffTables,
+            EV_EFI_VARIABLE_AUTHORITY => EventType::EfiVariableAuthority,
+            other => EventType::Unknown(other)
+        }
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match *self {
+            EventType::PrebootCert => EV_PREBOOT_CERT,
+            EventType::PostCode => EV_POST_CODE,
+            EventType::Unused => EV_UNUSED,
+            EventType::NoAction => EV_NO_ACTION,
+            EventType::Separator => EV_SEPARATOR,
+            EventType::Action => EV_ACTION,
+            EventType::EventTag => EV_EVENT_TAG,
+            EventType::SCrtmContents => EV_S_CRTM_CONTENTS,
+            EventType::SCrtmVersion => EV_S_CRTM_VERSION,
+            EventType::CpuMicrocode => EV_CPU_MICROCODE,
+            EventType::PlatformConfigFlags => EV_PLATFORM_CONFIG_FLAGS,
+            EventType::TableOfDevices => EV_TABLE_OF_DEVICES,
+            EventType::CompactHash => EV_COMPACT_HASH,
+            EventType::Ipl => EV_IPL,
+            EventType::IplPartitionData => EV_IPL_PARTITION_DATA,
+            EventType::NonhostCode => EV_NONHOST_CODE,
+            EventType::NonhostConfig => EV_NONHOST_CONFIG,
+            EventType::NonhostInfo => EV_NONHOST_INFO,
+            EventType::OmitBootDeviceEvents => EV_OMIT_BOOT_DEVICE_EVENTS,
+            EventType::EfiVariableDriverConfig => EV_EFI_VARIABLE_DRIVER_CONFIG,
+            EventType::EfiVariableBoot => EV_EFI_VARIABLE_BOOT,
+            EventType::EfiBootServicesApplication => EV_EFI_BOOT_SERVICES_APPLICATION,
+            EventType::EfiBootServicesDriver => EV_EFI_BOOT_SERVICES_DRIVER,
+            EventType::EfiRuntimeServicesDriver => EV_EFI_RUNTIME_SERVICES_DRIVER,
+            EventType::EfiGptEvent => EV_EFI_GPT_EVENT,
+            EventType::EfiAction => EV_EFI_ACTION,
+            EventType::EfiPlatformFirmwareBlob => EV_EFI_PLATFORM_FIRMWARE_BLOB,
+            EventType::EfiHandoffTables => EV_EFI_HANDOFF_TABLES,
+            EventType::EfiVariableAuthority => EV_EFI_VARIABLE_AUTHORITY,
+            EventType::Unknown(other) => other
+        }
+    }
+}
+
+/// An owned copy of a `TSS_PCR_EVENT`.
+///
+/// `pcr_value` is the digest that was extended into the PCR, not the value of
+/// the PCR after extension.
+#[derive(Clone)]
+pub struct PcrEvent {
+    pub version_info: TSS_VERSION,
+    pub pcr_index: u32,
+    pub event_type: EventType,
+    pub pcr_value: Vec<u8>,
+    pub event: Vec<u8>
+}
+
+impl PcrEvent {
+    pub fn new(version_info: TSS_VERSION, pcr_index: u32, event_type: EventType, event: &[u8]) -> PcrEvent {
+        PcrEvent {
+            version_info,
+            pcr_index,
+            event_type,
+            pcr_value: Vec::new(),
+            event: event.to_vec()
+        }
+    }
+
+    /// Copies the contents of a `TSS_PCR_EVENT` without taking ownership of
+    /// any of its buffers.
+    pub(crate) unsafe fn from_raw(raw_event: &TSS_PCR_EVENT) -> PcrEvent {
+        PcrEvent {
+            version_info: raw_event.versionInfo,
+            pcr_index: raw_event.ulPcrIndex,
+            event_type: EventType::from_u32(raw_event.eventType),
+            pcr_value: copy_raw_ptr_to_vec(raw_event.rgbPcrValue, raw_event.ulPcrValueLength as usize),
+            event: copy_raw_ptr_to_vec(raw_event.rgbEvent, raw_event.ulEventLength as usize)
+        }
+    }
+}
+
+// Events unloaded from tcsd have their digest and event data malloc'd rather
+// than tracked by the context, so Tspi_Context_FreeMemory can't release them.
+pub(crate) unsafe fn free_raw_event_data(raw_event: &TSS_PCR_EVENT) {
+    libc::free(raw_event.rgbPcrValue as *mut libc::c_void);
+    libc::free(raw_event.rgbEvent as *mut libc::c_void);
+}
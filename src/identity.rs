@@ -0,0 +1,142 @@
+use blob::{BlobReader, ParseError, TpmVersion};
+use pubkey::{RsaKeyParms, RsaPublicKey};
+
+pub const TPM_ALG_DES: u32 = 0x00000002;
+pub const TPM_ALG_3DES: u32 = 0x00000003;
+pub const TPM_ALG_AES128: u32 = 0x00000006;
+pub const TPM_ALG_AES192: u32 = 0x00000008;
+pub const TPM_ALG_AES256: u32 = 0x00000009;
+
+/// A `TPM_KEY_PARMS` for any algorithm, with its parameters undecoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyParms {
+    pub algorithm_id: u32,
+    pub enc_scheme: u16,
+    pub sig_scheme: u16,
+    pub parms: Vec<u8>
+}
+
+impl KeyParms {
+    pub(crate) fn read(reader: &mut BlobReader) -> Result<KeyParms, ParseError> {
+        let algorithm_id = reader.read_u32()?;
+        let enc_scheme = reader.read_u16()?;
+        let sig_scheme = reader.read_u16()?;
+        let parms = reader.read_sized_u32()?.to_vec();
+        Ok(KeyParms { algorithm_id, enc_scheme, sig_scheme, parms })
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.algorithm_id.to_be_bytes());
+        out.extend_from_slice(&self.enc_scheme.to_be_bytes());
+        out.extend_from_slice(&self.sig_scheme.to_be_bytes());
+        out.extend_from_slice(&(self.parms.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.parms);
+    }
+
+    /// Decodes the structure as an RSA `TPM_KEY_PARMS`.
+    pub fn rsa_key_parms(&self) -> Result<RsaKeyParms, ParseError> {
+        let mut encoded = Vec::new();
+        self.write(&mut encoded);
+        let mut reader = BlobReader::new(&encoded);
+        let parms = RsaKeyParms::read(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_RSA_KEY_PARMS"));
+        }
+        Ok(parms)
+    }
+
+    /// Decodes `parms` as a `TPM_SYMMETRIC_KEY_PARMS`.
+    pub fn symmetric_parms(&self) -> Result<SymmetricKeyParms, ParseError> {
+        let mut reader = BlobReader::new(&self.parms);
+        let key_length = reader.read_u32()?;
+        let block_size = reader.read_u32()?;
+        let iv = reader.read_sized_u32()?.to_vec();
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_SYMMETRIC_KEY_PARMS"));
+        }
+        Ok(SymmetricKeyParms { key_length, block_size, iv })
+    }
+}
+
+/// A `TPM_SYMMETRIC_KEY_PARMS`. Lengths are in bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymmetricKeyParms {
+    pub key_length: u32,
+    pub block_size: u32,
+    pub iv: Vec<u8>
+}
+
+impl SymmetricKeyParms {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.key_length.to_be_bytes());
+        out.extend_from_slice(&self.block_size.to_be_bytes());
+        out.extend_from_slice(&(self.iv.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.iv);
+        out
+    }
+}
+
+/// A `TPM_IDENTITY_REQ`, as returned by
+/// `TssTPM::collate_identity_request`. `asym_blob` holds the session key
+/// encrypted to the privacy CA and `sym_blob` the `TPM_IDENTITY_PROOF`
+/// encrypted with that key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdentityRequest {
+    pub asym_algorithm: KeyParms,
+    pub sym_algorithm: KeyParms,
+    pub asym_blob: Vec<u8>,
+    pub sym_blob: Vec<u8>
+}
+
+impl IdentityRequest {
+    pub fn parse(blob: &[u8]) -> Result<IdentityRequest, ParseError> {
+        let mut reader = BlobReader::new(blob);
+        let asym_blob_size = reader.read_u32()? as usize;
+        let sym_blob_size = reader.read_u32()? as usize;
+        let asym_algorithm = KeyParms::read(&mut reader)?;
+        let sym_algorithm = KeyParms::read(&mut reader)?;
+        let asym_blob = reader.read_bytes(asym_blob_size)?.to_vec();
+        let sym_blob = reader.read_bytes(sym_blob_size)?.to_vec();
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_IDENTITY_REQ"));
+        }
+        Ok(IdentityRequest { asym_algorithm, sym_algorithm, asym_blob, sym_blob })
+    }
+}
+
+/// A `TPM_IDENTITY_PROOF`, the decrypted contents of
+/// `IdentityRequest::sym_blob`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdentityProof {
+    pub version: TpmVersion,
+    pub identity_key: RsaPublicKey,
+    pub label: Vec<u8>,
+    /// The identity key's signature over a `TPM_IDENTITY_CONTENTS`.
+    pub identity_binding: Vec<u8>,
+    pub endorsement_credential: Vec<u8>,
+    pub platform_credential: Vec<u8>,
+    pub conformance_credential: Vec<u8>
+}
+
+impl IdentityProof {
+    pub fn parse(blob: &[u8]) -> Result<IdentityProof, ParseError> {
+        let mut reader = BlobReader::new(blob);
+        let version = reader.read_version()?;
+        let label_size = reader.read_u32()? as usize;
+        let identity_binding_size = reader.read_u32()? as usize;
+        let endorsement_size = reader.read_u32()? as usize;
+        let platform_size = reader.read_u32()? as usize;
+        let conformance_size = reader.read_u32()? as usize;
+        let identity_key = RsaPublicKey::read(&mut reader)?;
+        let label = reader.read_bytes(label_size)?.to_vec();
+        let identity_binding = reader.read_bytes(identity_binding_size)?.to_vec();
+        let endorsement_credential = reader.read_bytes(endorsement_size)?.to_vec();
+        let platform_credential = reader.read_bytes(platform_size)?.to_vec();
+        let conformance_credential = reader.read_bytes(conformance_size)?.to_vec();
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_IDENTITY_PROOF"));
+        }
+        Ok(IdentityProof { version, identity_key, label, identity_binding, endorsement_credential, platform_credential, conformance_credential })
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+use sha1::{Digest, Sha1};
+
+use blob::{BlobReader, ParseError};
+use replay::{sha1_extend, PCR_VALUE_LENGTH};
+
+/// The PCR Linux IMA extends its measurements into.
+pub const IMA_PCR: u32 = 10;
+
+pub const ASCII_RUNTIME_MEASUREMENTS_PATH: &str = "/sys/kernel/security/ima/ascii_runtime_measurements";
+pub const BINARY_RUNTIME_MEASUREMENTS_PATH: &str = "/sys/kernel/security/ima/binary_runtime_measurements";
+
+// The legacy ima template hashes the file name zero-padded to this length
+const IMA_EVENT_NAME_LEN_MAX: usize = 255;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImaTemplate {
+    Ima, ImaNg, ImaSig
+}
+
+impl ImaTemplate {
+    fn from_name(name: &[u8]) -> Option<ImaTemplate> {
+        match name {
+            b"ima" => Some(ImaTemplate::Ima),
+            b"ima-ng" => Some(ImaTemplate::ImaNg),
+            b"ima-sig" => Some(ImaTemplate::ImaSig),
+            _ => None
+        }
+    }
+}
+
+/// One entry of the IMA measurement list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImaEntry {
+    pub pcr_index: u32,
+    pub template_hash: Vec<u8>,
+    pub template: ImaTemplate,
+    /// Name of the file hash algorithm, always `sha1` for the ima template.
+    pub hash_algorithm: String,
+    pub file_hash: Vec<u8>,
+    pub file_name: String,
+    /// The file's signature, for ima-sig entries that have one.
+    pub signature: Option<Vec<u8>>
+}
+
+impl ImaEntry {
+    /// A violation is logged with an all-zero template hash, but extends
+    /// the PCR with all ones so that it can never be replayed cleanly.
+    pub fn is_violation(&self) -> bool {
+        self.template_hash.iter().all(|byte| *byte == 0)
+    }
+
+    /// Recomputes the template hash from the entry's fields.
+    pub fn compute_template_hash(&self) -> Vec<u8> {
+        let mut hasher = Sha1::new();
+        match self.template {
+            ImaTemplate::Ima => {
+                let mut file_name = self.file_name.as_bytes().to_vec();
+                file_name.resize(IMA_EVENT_NAME_LEN_MAX + 1, 0);
+                hasher.update(&self.file_hash);
+                hasher.update(&file_name);
+            },
+            ImaTemplate::ImaNg | ImaTemplate::ImaSig => {
+                let mut digest_field = Vec::new();
+                digest_field.extend_from_slice(self.hash_algorithm.as_bytes());
+                digest_field.extend_from_slice(b":\0");
+                digest_field.extend_from_slice(&self.file_hash);
+                let mut name_field = self.file_name.as_bytes().to_vec();
+                name_field.push(0);
+                let mut fields = vec![digest_field, name_field];
+                if self.template == ImaTemplate::ImaSig {
+                    fields.push(self.signature.clone().unwrap_or_default());
+                }
+                for field in fields {
+                    hasher.update((field.len() as u32).to_le_bytes());
+                    hasher.update(&field);
+                }
+            }
+        }
+        hasher.finalize().to_vec()
+    }
+
+    fn extend_digest(&self) -> Vec<u8> {
+        if self.is_violation() {
+            vec![0xff; PCR_VALUE_LENGTH]
+        } else {
+            self.template_hash.clone()
+        }
+    }
+}
+
+/// Parses `ascii_runtime_measurements`.
+///
+/// File names containing spaces are kept intact, except that for ima-sig
+/// entries a trailing hex token is always taken to be the signature.
+pub fn parse_ascii_measurements(text: &str) -> Result<Vec<ImaEntry>, ParseError> {
+    let mut entries = Vec::new();
+    let mut line_offset = 0;
+    for line in text.split('\n') {
+        let offset = line_offset;
+        line_offset += line.len() + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |field| ParseError::InvalidValue { offset, field };
+        let mut fields = line.splitn(5, ' ');
+        let pcr_index = fields.next().and_then(|field| field.parse().ok())
+            .ok_or_else(|| invalid("PCR index"))?;
+        let template_hash = fields.next().and_then(decode_hex)
+            .ok_or_else(|| invalid("template hash"))?;
+        let template = fields.next().and_then(|field| ImaTemplate::from_name(field.as_bytes()))
+            .ok_or_else(|| invalid("template name"))?;
+        let digest = fields.next().ok_or_else(|| invalid("file hash"))?;
+        let mut file_name = fields.next().ok_or_else(|| invalid("file name"))?;
+
+        let (hash_algorithm, file_hash) = match template {
+            ImaTemplate::Ima => ("sha1", digest),
+            _ => {
+                let mut parts = digest.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(algorithm), Some(hash)) => (algorithm, hash),
+                    _ => return Err(invalid("file hash"))
+                }
+            }
+        };
+        let file_hash = decode_hex(file_hash).ok_or_else(|| invalid("file hash"))?;
+
+        let mut signature = None;
+        if template == ImaTemplate::ImaSig {
+            if let Some(split) = file_name.rfind(' ') {
+                if let Some(decoded) = decode_hex(&file_name[split + 1..]) {
+                    signature = Some(decoded);
+                    file_name = &file_name[..split];
+                }
+            }
+        }
+
+        entries.push(ImaEntry {
+            pcr_index,
+            template_hash,
+            template,
+            hash_algorithm: hash_algorithm.to_string(),
+            file_hash,
+            file_name: file_name.to_string(),
+            signature
+        });
+    }
+    Ok(entries)
+}
+
+/// Parses `binary_runtime_measurements`, which is in the byte order of the
+/// host that produced it. Only little-endian hosts are supported.
+pub fn parse_binary_measurements(data: &[u8]) -> Result<Vec<ImaEntry>, ParseError> {
+    let mut reader = BlobReader::new(data);
+    let mut entries = Vec::new();
+    while !reader.is_empty() {
+        let pcr_index = reader.read_u32_le()?;
+        let template_hash = reader.read_digest()?.to_vec();
+        let name_length = reader.read_u32_le()? as usize;
+        let template = ImaTemplate::from_name(reader.read_bytes(name_length)?)
+            .ok_or_else(|| reader.invalid("template name"))?;
+
+        let entry = match template {
+            ImaTemplate::Ima => {
+                let file_hash = reader.read_digest()?.to_vec();
+                let file_name = read_field_le(&mut reader)?;
+                ImaEntry {
+                    pcr_index,
+                    template_hash,
+                    template,
+                    hash_algorithm: "sha1".to_string(),
+                    file_hash,
+                    file_name: decode_file_name(file_name),
+                    signature: None
+                }
+            },
+            ImaTemplate::ImaNg | ImaTemplate::ImaSig => {
+                let template_data = read_field_le(&mut reader)?;
+                let mut fields = BlobReader::new(template_data);
+                let digest_field = read_field_le(&mut fields)?;
+                let split = digest_field.windows(2).position(|window| window == b":\0")
+                    .ok_or_else(|| fields.invalid("d-ng"))?;
+                let file_name = read_field_le(&mut fields)?;
+                let signature = if template == ImaTemplate::ImaSig {
+                    let signature = read_field_le(&mut fields)?;
+                    if signature.is_empty() { None } else { Some(signature.to_vec()) }
+                } else {
+                    None
+                };
+                ImaEntry {
+                    pcr_index,
+                    template_hash,
+                    template,
+                    hash_algorithm: String::from_utf8_lossy(&digest_field[..split]).into_owned(),
+                    file_hash: digest_field[split + 2..].to_vec(),
+                    file_name: decode_file_name(file_name),
+                    signature
+                }
+            }
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn read_field_le<'a>(reader: &mut BlobReader<'a>) -> Result<&'a [u8], ParseError> {
+    let length = reader.read_u32_le()? as usize;
+    reader.read_bytes(length)
+}
+
+fn decode_file_name(file_name: &[u8]) -> String {
+    let end = file_name.iter().position(|byte| *byte == 0).unwrap_or(file_name.len());
+    String::from_utf8_lossy(&file_name[..end]).into_owned()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|i| text.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum ImaError {
+    /// The template hash of the entry at `entry_index` doesn't match its
+    /// contents, so the file hash it reports can't be trusted.
+    TemplateHashMismatch { entry_index: usize },
+    /// The replayed measurement list doesn't produce the PCR value read from
+    /// the TPM.
+    PcrMismatch { expected: Vec<u8>, replayed: Vec<u8> }
+}
+
+impl fmt::Display for ImaError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImaError::TemplateHashMismatch { entry_index } =>
+                write!(fmt, "template hash of IMA entry {} does not match its contents", entry_index),
+            ImaError::PcrMismatch { .. } =>
+                write!(fmt, "IMA measurement list does not match PCR {}", IMA_PCR)
+        }
+    }
+}
+
+impl error::Error for ImaError {}
+
+/// Result of replaying an IMA measurement list.
+#[derive(Clone, Debug)]
+pub struct ImaReplay {
+    /// The value PCR 10 should hold if the list is complete.
+    pub pcr_value: Vec<u8>,
+    /// Every hash measured for each file, in measurement order.
+    pub file_hashes: BTreeMap<String, Vec<Vec<u8>>>
+}
+
+/// Replays `entries` into a fresh PCR 10, checking each entry's template
+/// hash along the way. Entries for other PCRs are ignored.
+pub fn replay_ima(entries: &[ImaEntry]) -> Result<ImaReplay, ImaError> {
+    let mut pcr_value = vec![0; PCR_VALUE_LENGTH];
+    let mut file_hashes: BTreeMap<String, Vec<Vec<u8>>> = BTreeMap::new();
+    for (entry_index, entry) in entries.iter().enumerate() {
+        if entry.pcr_index != IMA_PCR {
+            continue;
+        }
+        if !entry.is_violation() && entry.compute_template_hash() != entry.template_hash {
+            return Err(ImaError::TemplateHashMismatch { entry_index });
+        }
+        pcr_value = sha1_extend(&pcr_value, &entry.extend_digest());
+        file_hashes.entry(entry.file_name.clone()).or_default().push(entry.file_hash.clone());
+    }
+    Ok(ImaReplay { pcr_value, file_hashes })
+}
+
+/// Replays `entries` and checks the result against a PCR 10 value, such as
+/// one read with `TssTPM::pcr_read(IMA_PCR)`.
+///
+/// The list keeps growing while the system runs, so a mismatch may just
+/// mean a measurement landed between reading the list and the PCR.
+pub fn verify_ima(entries: &[ImaEntry], pcr_value: &[u8]) -> Result<ImaReplay, ImaError> {
+    let replay = replay_ima(entries)?;
+    if replay.pcr_value != pcr_value {
+        return Err(ImaError::PcrMismatch { expected: pcr_value.to_vec(), replayed: replay.pcr_value });
+    }
+    Ok(replay)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::hex;
+    use super::*;
+
+    // boot_aggregate, ima-ng, ima-sig with and without a signature, a file
+    // name with a space and a violation, all extended into PCR 10
+    const ASCII_LIST: &str = include_str!("../testdata/ascii_runtime_measurements");
+    // The same list in the kernel's binary format
+    const BINARY_LIST: &[u8] = include_bytes!("../testdata/binary_runtime_measurements");
+    const PCR_10: &str = "81374b58d37ec6256d61ea913c64f044c4168e2a";
+
+    #[test]
+    fn parses_ascii_list() {
+        let entries = parse_ascii_measurements(ASCII_LIST).unwrap();
+        assert_eq!(entries.len(), 6);
+        let templates: Vec<ImaTemplate> = entries.iter().map(|entry| entry.template).collect();
+        assert_eq!(templates, vec![ImaTemplate::Ima, ImaTemplate::ImaNg, ImaTemplate::ImaNg, ImaTemplate::ImaSig, ImaTemplate::ImaSig, ImaTemplate::ImaNg]);
+        assert_eq!(entries[0].file_name, "boot_aggregate");
+        assert_eq!(entries[0].hash_algorithm, "sha1");
+        assert_eq!(entries[1].hash_algorithm, "sha256");
+        assert_eq!(entries[1].file_hash.len(), 32);
+        assert_eq!(entries[2].file_name, "/home/user/my file.txt");
+        assert_eq!(entries[3].signature, Some(hex("030204a1b2c3d400085a5a5a5a5a5a5a5a")));
+        assert_eq!(entries[4].signature, None);
+        assert!(entries[5].is_violation());
+        for entry in &entries[..5] {
+            assert_eq!(entry.compute_template_hash(), entry.template_hash);
+        }
+    }
+
+    #[test]
+    fn binary_list_matches_ascii_list() {
+        assert_eq!(parse_binary_measurements(BINARY_LIST).unwrap(), parse_ascii_measurements(ASCII_LIST).unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_binary_list() {
+        match parse_binary_measurements(&BINARY_LIST[..BINARY_LIST.len() - 1]) {
+            Err(ParseError::UnexpectedEnd { .. }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn replays_into_pcr_10() {
+        let entries = parse_ascii_measurements(ASCII_LIST).unwrap();
+        let replay = verify_ima(&entries, &hex(PCR_10)).unwrap();
+        assert_eq!(replay.file_hashes["boot_aggregate"], vec![entries[0].file_hash.clone()]);
+        assert_eq!(replay.file_hashes.len(), 6);
+    }
+
+    #[test]
+    fn violation_extends_all_ones() {
+        let entries = parse_ascii_measurements(ASCII_LIST).unwrap();
+        let before = replay_ima(&entries[..5]).unwrap().pcr_value;
+        assert_eq!(sha1_extend(&before, &[0xff; 20]), hex(PCR_10));
+    }
+
+    #[test]
+    fn ignores_other_pcrs() {
+        let mut entries = parse_ascii_measurements(ASCII_LIST).unwrap();
+        let mut other = entries[1].clone();
+        other.pcr_index = 11;
+        other.template_hash = vec![1; 20];
+        entries.insert(1, other);
+        assert_eq!(replay_ima(&entries).unwrap().pcr_value, hex(PCR_10));
+    }
+
+    #[test]
+    fn detects_tampered_entry() {
+        let mut entries = parse_ascii_measurements(ASCII_LIST).unwrap();
+        entries[3].file_hash[0] ^= 1;
+        match replay_ima(&entries) {
+            Err(ImaError::TemplateHashMismatch { entry_index: 3 }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn detects_pcr_mismatch() {
+        let entries = parse_ascii_measurements(ASCII_LIST).unwrap();
+        match verify_ima(&entries[..5], &hex(PCR_10)) {
+            Err(ImaError::PcrMismatch { expected, .. }) => assert_eq!(expected, hex(PCR_10)),
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+}
@@ -1,17 +1,77 @@
+extern crate aes;
+extern crate cbc;
+extern crate crc32fast;
+extern crate des;
+extern crate libc;
+#[cfg(test)]
+extern crate rand;
+#[cfg(feature = "rand_core")]
+extern crate rand_core;
+extern crate rsa;
+extern crate sha1;
 extern crate trousers_sys;
+extern crate x509_cert;
 
+use std::cell::Cell;
+use std::cmp;
 use std::error;
 use std::ffi;
 use std::fmt;
+use std::ptr;
 use std::slice;
 use trousers_sys::trousers::*;
 use trousers_sys::tspi::*;
 
+mod blob;
+pub mod bios_log;
+pub mod capability;
+pub mod certify;
+pub mod counter;
+pub mod ek;
+pub mod ekcert;
+pub mod event_log;
+pub mod identity;
+pub mod ima;
+mod memory;
+pub mod nv;
+pub mod privacy_ca;
+pub mod pubkey;
+pub mod quote;
+pub mod random;
+pub mod record_store;
+pub mod replay;
+pub mod self_test;
+pub mod status;
+pub mod ticks;
+pub mod verify;
+
+#[cfg(test)]
+mod test_util;
+
+pub use blob::{ParseError, TpmVersion};
+pub use event_log::{EventType, PcrEvent};
+pub use identity::{IdentityProof, IdentityRequest};
+pub use nv::{NvDataPublic, NvIndex, NvPermissions};
+pub use pubkey::RsaPublicKey;
+pub use ek::EkResetSecret;
+pub use counter::{CounterError, CounterValue, NvSequenceCounter};
+pub use certify::{CertifiedKey, CertifyInfo};
+pub use capability::{CapVersionInfo, PermanentFlags, SoftwareStackInfo, StClearFlags};
+pub use random::TpmRng;
+pub use record_store::{NvRecordStore, NvSlot, RecordStoreError};
+pub use quote::{PcrComposite, Quote, Quote2, QuoteInfo, QuoteInfo2};
+pub use self_test::{CertifiedSelfTest, SelfTestResult};
+pub use status::{TpmStatusAuth, TpmStatusError, TpmStatusFlag};
+pub use ticks::{CurrentTicks, TickClock, TickError};
+pub use verify::VerifyError;
+
 pub type TssFlag = u32;
 pub type TssHObject = u32;
 pub type TssHContext = TssHObject;
 pub type TssHTPM = TssHObject;
 pub type TssHPCRS = TssHObject;
+pub type TssHNVStore = TssHObject;
+pub type TssHEncData = TssHObject;
 pub type TssResult = u32;
 pub type TssUnicode = u16;
 
@@ -47,13 +107,79 @@ impl error::Error for TssError {
 // TODO move constants into a separate module?
 
 pub const TSS_TSPATTRIB_KEY_INFO: TssFlag = 0x00000080;
+pub const TSS_TSPATTRIB_KEYINFO_USAGE: TssFlag = 0x00000100;
 pub const TSS_TSPATTRIB_KEYINFO_ALGORITHM: TssFlag = 0x00000280;
 pub const TSS_TSPATTRIB_RSAKEY_INFO: TssFlag = 0x00000140;
 pub const TSS_TSPATTRIB_KEYINFO_RSA_PRIMES: TssFlag = 0x00004000;
+pub const TSS_TSPATTRIB_KEY_BLOB: TssFlag = 0x00000040;
+pub const TSS_TSPATTRIB_KEYBLOB_BLOB: TssFlag = 0x00000008;
+pub const TSS_TSPATTRIB_KEYBLOB_PUBLIC_KEY: TssFlag = 0x00000010;
+pub const TSS_TSPATTRIB_KEYBLOB_PRIVATE_KEY: TssFlag = 0x00000028;
+pub const TSS_TSPATTRIB_ENCDATA_BLOB: TssFlag = 0x00000008;
+pub const TSS_TSPATTRIB_ENCDATABLOB_BLOB: TssFlag = 0x00000001;
 pub const TSS_ALG_RSA: TssFlag = 0x20;
+pub const TSS_ALG_DES: TssFlag = 0x21;
+pub const TSS_ALG_3DES: TssFlag = 0x22;
+pub const TSS_ALG_SHA: TssFlag = 0x23;
+pub const TSS_ALG_HMAC: TssFlag = 0x24;
+pub const TSS_ALG_AES128: TssFlag = 0x25;
+pub const TSS_ALG_MGF1: TssFlag = 0x26;
+pub const TSS_ALG_AES192: TssFlag = 0x27;
+pub const TSS_ALG_AES256: TssFlag = 0x28;
+pub const TSS_ALG_XOR: TssFlag = 0x29;
+pub const TSS_ALG_AES: TssFlag = TSS_ALG_AES128;
+
+pub const TSS_TPMCAP_ORD: TssFlag = 0x10;
+pub const TSS_TPMCAP_ALG: TssFlag = 0x11;
+pub const TSS_TPMCAP_FLAG: TssFlag = 0x12;
+pub const TSS_TPMCAP_PROPERTY: TssFlag = 0x13;
+pub const TSS_TPMCAP_VERSION: TssFlag = 0x14;
+pub const TSS_TPMCAP_NV_LIST: TssFlag = 0x15;
+pub const TSS_TPMCAP_NV_INDEX: TssFlag = 0x16;
+pub const TSS_TPMCAP_MFR: TssFlag = 0x17;
+pub const TSS_TPMCAP_SYM_MODE: TssFlag = 0x18;
+pub const TSS_TPMCAP_HANDLE: TssFlag = 0x19;
+pub const TSS_TPMCAP_TRANS_ES: TssFlag = 0x1a;
+pub const TSS_TPMCAP_AUTH_ENCRYPT: TssFlag = 0x1b;
+pub const TSS_TPMCAP_SELECT_SIZE: TssFlag = 0x1c;
+pub const TSS_TPMCAP_DA_LOGIC: TssFlag = 0x1d;
+pub const TSS_TPMCAP_VERSION_VAL: TssFlag = 0x1e;
+
+pub const TSS_TPMCAP_PROP_PCR: TssFlag = 0x10;
+pub const TSS_TPMCAP_PROP_DIR: TssFlag = 0x11;
+pub const TSS_TPMCAP_PROP_MANUFACTURER: TssFlag = 0x12;
+pub const TSS_TPMCAP_PROP_SLOTS: TssFlag = 0x13;
+pub const TSS_TPMCAP_PROP_KEYS: TssFlag = TSS_TPMCAP_PROP_SLOTS;
+pub const TSS_TPMCAP_PROP_OWNER: TssFlag = 0x16;
+pub const TSS_TPMCAP_PROP_MAXKEYS: TssFlag = 0x18;
+pub const TSS_TPMCAP_PROP_COUNTERS: TssFlag = 0x25;
+pub const TSS_TPMCAP_PROP_MAXCOUNTERS: TssFlag = 0x26;
+pub const TSS_TPMCAP_PROP_ACTIVECOUNTER: TssFlag = 0x27;
+pub const TSS_TPMCAP_PROP_MAXNVAVAILABLE: TssFlag = 0x2e;
+pub const TSS_TPMCAP_PROP_INPUTBUFFERSIZE: TssFlag = 0x2f;
+pub const TSS_TPMCAP_PROP_LOCALITIES_AVAIL: TssFlag = 0x32;
+
+pub const TSS_TCSCAP_ALG: TssFlag = 0x01;
+pub const TSS_TCSCAP_VERSION: TssFlag = 0x02;
+pub const TSS_TCSCAP_CACHING: TssFlag = 0x03;
+pub const TSS_TCSCAP_PERSSTORAGE: TssFlag = 0x04;
+pub const TSS_TCSCAP_MANUFACTURER: TssFlag = 0x05;
+pub const TSS_TCSCAP_PROP_MANUFACTURER_STR: TssFlag = 0x102;
+pub const TSS_TCSCAP_PROP_MANUFACTURER_ID: TssFlag = 0x103;
+
+pub const TSS_TSPCAP_ALG: TssFlag = 0x10;
+pub const TSS_TSPCAP_VERSION: TssFlag = 0x11;
+pub const TSS_TSPCAP_PERSSTORAGE: TssFlag = 0x12;
+pub const TSS_TSPCAP_MANUFACTURER: TssFlag = 0x13;
+pub const TSS_TSPCAP_PROP_MANUFACTURER_ID: TssFlag = 0x102;
+pub const TSS_TSPCAP_PROP_MANUFACTURER_STR: TssFlag = 0x103;
 
 pub const TSS_SUCCESS: TssResult = 0;
 
+const TSS_LAYER_TSP: TssResult = 0x3000;
+const TSS_E_BAD_PARAMETER: TssResult = 0x003;
+const TSS_E_INTERNAL_ERROR: TssResult = 0x004;
+
 pub const TSS_UUID_SRK: TSS_UUID = TSS_UUID { ulTimeLow: 0, usTimeMid: 0, usTimeHigh: 0, bClockSeqHigh: 0, bClockSeqLow: 0, rgbNode: [0, 0, 0, 0, 0, 1] };
 
 pub const TSS_PS_TYPE_USER: TssFlag = 1;
@@ -61,16 +187,41 @@ pub const TSS_PS_TYPE_SYSTEM: TssFlag = 2;
 
 const TSS_OBJECT_TYPE_POLICY: TssFlag = 1;
 const TSS_OBJECT_TYPE_RSAKEY: TssFlag = 2;
+const TSS_OBJECT_TYPE_ENCDATA: TssFlag = 3;
 const TSS_OBJECT_TYPE_PCRS: TssFlag = 4;
+const TSS_OBJECT_TYPE_NV: TssFlag = 7;
 
 const TSS_POLICY_USAGE: TssFlag = 1;
 const TSS_POLICY_MIGRATION: TssFlag = 2;
 const TSS_POLICY_OPERATOR: TssFlag = 3;
+const TSS_SECRET_MODE_NONE: TssFlag = 0x00000800;
+const TSS_SECRET_MODE_SHA1: TssFlag = 0x00001000;
+const TSS_SECRET_MODE_PLAIN: TssFlag = 0x00001800;
+const TSS_SECRET_MODE_POPUP: TssFlag = 0x00002000;
+
+const TSS_ENCDATA_SEAL: TssFlag = 1;
+const TSS_ENCDATA_BIND: TssFlag = 2;
+const TSS_ENCDATA_LEGACY: TssFlag = 3;
+
 const TSS_PCRS_STRUCT_DEFAULT: TssFlag = 0;
 const TSS_PCRS_STRUCT_INFO: TssFlag = 1;
 const TSS_PCRS_STRUCT_INFO_LONG: TssFlag = 2;
 const TSS_PCRS_STRUCT_INFO_SHORT: TssFlag = 3;
 
+const TSS_TSPATTRIB_NV_INDEX: TssFlag = 0x00000001;
+const TSS_TSPATTRIB_NV_PERMISSIONS: TssFlag = 0x00000002;
+const TSS_TSPATTRIB_NV_DATASIZE: TssFlag = 0x00000004;
+
+// TPM_StirRandom takes fewer than 256 bytes.
+const STIR_RANDOM_MAX: usize = 255;
+
+// Room left in the TPM's input buffer for the header and authorization
+// session of an NV command.
+const NV_COMMAND_OVERHEAD: u32 = 128;
+
+pub const TSS_PCRS_DIRECTION_CREATION: u32 = 1;
+pub const TSS_PCRS_DIRECTION_RELEASE: u32 = 2;
+
 const TSS_KEY_NO_AUTHORIZATION: TssFlag = 0x00000000;
 const TSS_KEY_AUTHORIZATION: TssFlag = 0x00000001;
 const TSS_KEY_AUTHORIZATION_PRIV_USE_ONLY: TssFlag = 0x00000002;
@@ -91,6 +242,15 @@ const TSS_KEY_TYPE_BIND: TssFlag = 0x00000050;
 const TSS_KEY_TYPE_LEGACY: TssFlag = 0x00000060;
 const TSS_KEY_TYPE_MIGRATE: TssFlag = 0x00000070;
 
+// Key usages reported by TSS_TSPATTRIB_KEYINFO_USAGE
+const TSS_KEYUSAGE_BIND: TssFlag = 0x00;
+const TSS_KEYUSAGE_IDENTITY: TssFlag = 0x01;
+const TSS_KEYUSAGE_LEGACY: TssFlag = 0x02;
+const TSS_KEYUSAGE_SIGN: TssFlag = 0x03;
+const TSS_KEYUSAGE_STORAGE: TssFlag = 0x04;
+const TSS_KEYUSAGE_AUTHCHANGE: TssFlag = 0x05;
+const TSS_KEYUSAGE_MIGRATE: TssFlag = 0x06;
+
 const TSS_KEY_SIZE_DEFAULT: TssFlag = 0x00000000;
 const TSS_KEY_SIZE_512: TssFlag = 0x00000100;
 const TSS_KEY_SIZE_1024: TssFlag = 0x00000200;
@@ -103,10 +263,19 @@ const TSS_KEY_STRUCT_DEFAULT: TssFlag = 0x00000000;
 const TSS_KEY_STRUCT_KEY: TssFlag = 0x00004000;
 const TSS_KEY_STRUCT_KEY12: TssFlag = 0x00008000;
 
+const TSS_KEY_TSP_SRK: TssFlag = 0x04000000;
+
 pub enum TssPolicyInitFlag {
     Usage, Migration, Operator
 }
 
+pub enum TssSecretMode {
+    None = TSS_SECRET_MODE_NONE as isize,
+    Sha1 = TSS_SECRET_MODE_SHA1 as isize,
+    Plain = TSS_SECRET_MODE_PLAIN as isize,
+    Popup = TSS_SECRET_MODE_POPUP as isize
+}
+
 pub enum TssKeySize {
     Default = TSS_KEY_SIZE_DEFAULT as isize,
     Size512 = TSS_KEY_SIZE_512 as isize,
@@ -116,10 +285,20 @@ pub enum TssKeySize {
     Size8192 = TSS_KEY_SIZE_8192 as isize,
     Size16384 = TSS_KEY_SIZE_16384 as isize
 }
+pub enum TssEncDataType {
+    Seal = TSS_ENCDATA_SEAL as isize,
+    Bind = TSS_ENCDATA_BIND as isize,
+    Legacy = TSS_ENCDATA_LEGACY as isize
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TssKeyType {
     Signing = TSS_KEY_TYPE_SIGNING as isize,
     Storage = TSS_KEY_TYPE_STORAGE as isize,
     Identity = TSS_KEY_TYPE_IDENTITY as isize,
+    /// The usage of the ephemeral key behind
+    /// `TssChangeAuthAsym::change_auth_asym`. The TPM makes these keys
+    /// itself and refuses to create them with `create_rsakey`; they can
+    /// only be recognized with `TssRsaKey::get_key_type`.
     AuthChange = TSS_KEY_TYPE_AUTHCHANGE as isize,
     Bind = TSS_KEY_TYPE_BIND as isize,
     Legacy = TSS_KEY_TYPE_LEGACY as isize,
@@ -150,6 +329,34 @@ pub trait TssObject {
     fn set_attrib_data(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib_data: &[u8]) -> Result<(), TssError>;
 }
 
+/// Objects whose authorization secret `Tspi_ChangeAuth` can change: the TPM
+/// (the owner secret), keys and encrypted data.
+pub trait TssChangeAuth: TssObject + Sized {
+    /// Changes the authorization secret of this object to the one in
+    /// `new_policy`, which is then assigned to it. `parent` is the key the
+    /// object is wrapped by, or the TPM for the SRK; its usage policy and
+    /// this object's current policy authorize the change. For the TPM
+    /// object this changes the owner secret and `parent` is ignored.
+    ///
+    /// Keys and encrypted data are re-wrapped: store their new blobs, from
+    /// `TssRsaKey::get_key_blob` or `TssEncData::get_blob`, in place of the
+    /// old ones, which still hold the old secret.
+    fn change_auth(&self, parent: &dyn TssObject, new_policy: &TssPolicy) -> Result<(), TssError> {
+        change_auth_impl(self, parent, new_policy)
+    }
+}
+
+/// Objects whose authorization secret `Tspi_ChangeAuthAsym` can change:
+/// keys and encrypted data.
+pub trait TssChangeAuthAsym: TssObject + Sized {
+    /// Like `TssChangeAuth::change_auth`, but sends the new secret to the
+    /// TPM encrypted with an ephemeral `TssKeyType::AuthChange` key, which
+    /// the TPM generates and certifies with `identity_key`.
+    fn change_auth_asym(&self, parent: &dyn TssObject, identity_key: &TssRsaKey, new_policy: &TssPolicy) -> Result<(), TssError> {
+        change_auth_asym_impl(self, parent, identity_key, new_policy)
+    }
+}
+
 pub struct TssContext {
     pub handle: u32
 }
@@ -174,6 +381,19 @@ pub struct TssRsaKey<'context> {
     pub handle: TssHObject
 }
 
+pub struct TssNvStore<'context> {
+    pub context: &'context TssContext,
+    pub handle: TssHNVStore,
+    // The largest NV read or write that fits the TPM's buffers, queried
+    // on first use
+    chunk_size: Cell<Option<usize>>
+}
+
+pub struct TssEncData<'context> {
+    pub context: &'context TssContext,
+    pub handle: TssHEncData
+}
+
 pub struct TssValidation {
     pub version_info: TSS_VERSION,
     pub external_data: Vec<u8>,
@@ -219,6 +439,9 @@ impl<'c> TcpaPcrInfoAny for TssPCRCompositeInfoShort<'c> {
 }
 
 fn copy_raw_ptr_to_vec(ptr: *const u8, length: usize) -> Vec<u8> {
+    if ptr.is_null() {
+        return Vec::new();
+    }
     let ptr_slice = unsafe {
         slice::from_raw_parts(ptr, length)
     };
@@ -229,7 +452,38 @@ fn copy_raw_ptr_to_vec(ptr: *const u8, length: usize) -> Vec<u8> {
     vec
 }
 
-fn set_attrib_uint32_impl(object: &TssObject, attrib_flag: TssFlag, sub_flag: TssFlag, attrib: u32) -> Result<(), TssError> {
+// Used when the TSS hands back data we can't make sense of
+fn internal_error() -> TssError {
+    TssError { result: TSS_LAYER_TSP | TSS_E_INTERNAL_ERROR }
+}
+
+fn bad_parameter() -> TssError {
+    TssError { result: TSS_LAYER_TSP | TSS_E_BAD_PARAMETER }
+}
+
+// The TSS passes UINT32 sub-capabilities and properties in host byte order
+fn decode_cap_uint32(data: &[u8]) -> Result<u32, TssError> {
+    if data.len() != 4 {
+        return Err(internal_error());
+    }
+    Ok(u32::from_ne_bytes([data[0], data[1], data[2], data[3]]))
+}
+
+fn decode_cap_bool(data: &[u8]) -> Result<bool, TssError> {
+    match data.first() {
+        Some(value) => Ok(*value != 0),
+        None => Err(internal_error())
+    }
+}
+
+fn decode_cap_version(data: &[u8]) -> Result<TpmVersion, TssError> {
+    if data.len() != 4 {
+        return Err(internal_error());
+    }
+    Ok(TpmVersion { major: data[0], minor: data[1], rev_major: data[2], rev_minor: data[3] })
+}
+
+fn set_attrib_uint32_impl(object: &dyn TssObject, attrib_flag: TssFlag, sub_flag: TssFlag, attrib: u32) -> Result<(), TssError> {
     let result = unsafe {
         Tspi_SetAttribUint32(object.get_handle(), attrib_flag, sub_flag, attrib)
     };
@@ -238,7 +492,50 @@ fn set_attrib_uint32_impl(object: &TssObject, attrib_flag: TssFlag, sub_flag: Ts
     }
     Ok(())
 }
-fn set_attrib_data_impl(object: &TssObject, attrib_flag: TssFlag, sub_flag: TssFlag, attrib_data: &[u8]) -> Result<(), TssError> {
+fn get_attrib_uint32_impl(object: &dyn TssObject, attrib_flag: TssFlag, sub_flag: TssFlag) -> Result<u32, TssError> {
+    let mut attrib = 0;
+    let result = unsafe {
+        Tspi_GetAttribUint32(object.get_handle(), attrib_flag, sub_flag, &mut attrib)
+    };
+    if result != TSS_SUCCESS {
+        return Err(TssError { result });
+    }
+    Ok(attrib)
+}
+fn get_attrib_data_impl(object: &dyn TssObject, context: &TssContext, attrib_flag: TssFlag, sub_flag: TssFlag) -> Result<Vec<u8>, TssError> {
+    let mut attrib_data_length = 0;
+    let mut attrib_data_ptr = ptr::null_mut();
+    let result = unsafe {
+        Tspi_GetAttribData(object.get_handle(), attrib_flag, sub_flag, &mut attrib_data_length, &mut attrib_data_ptr)
+    };
+    if result != TSS_SUCCESS {
+        return Err(TssError { result });
+    }
+    let attrib_data = copy_raw_ptr_to_vec(attrib_data_ptr, attrib_data_length as usize);
+    unsafe {
+        Tspi_Context_FreeMemory(context.handle, attrib_data_ptr);
+    }
+    Ok(attrib_data)
+}
+fn change_auth_impl(object: &dyn TssObject, parent: &dyn TssObject, new_policy: &TssPolicy) -> Result<(), TssError> {
+    let result = unsafe {
+        Tspi_ChangeAuth(object.get_handle(), parent.get_handle(), new_policy.handle)
+    };
+    if result != TSS_SUCCESS {
+        return Err(TssError { result });
+    }
+    Ok(())
+}
+fn change_auth_asym_impl(object: &dyn TssObject, parent: &dyn TssObject, identity_key: &TssRsaKey, new_policy: &TssPolicy) -> Result<(), TssError> {
+    let result = unsafe {
+        Tspi_ChangeAuthAsym(object.get_handle(), parent.get_handle(), identity_key.handle, new_policy.handle)
+    };
+    if result != TSS_SUCCESS {
+        return Err(TssError { result });
+    }
+    Ok(())
+}
+fn set_attrib_data_impl(object: &dyn TssObject, attrib_flag: TssFlag, sub_flag: TssFlag, attrib_data: &[u8]) -> Result<(), TssError> {
     let result = unsafe {
         // TODO is usize to u32 cast safe?
         Tspi_SetAttribData(object.get_handle(), attrib_flag, sub_flag, attrib_data.len() as u32, attrib_data.as_ptr() as *mut u8)
@@ -294,6 +591,68 @@ impl TssContext {
         Ok(TssRsaKey { context: self, handle: handle })
     }
 
+    /// Loads a wrapped `TPM_KEY` or `TPM_KEY12` blob, such as one saved
+    /// from `TssRsaKey::get_key_blob`, into the TPM under `unwrapping_key`.
+    pub fn load_key_by_blob(&self, unwrapping_key: &TssRsaKey, blob: &[u8]) -> Result<TssRsaKey<'_>, TssError> {
+        let mut handle = 0;
+        let result = unsafe {
+            Tspi_Context_LoadKeyByBlob(self.handle, unwrapping_key.handle, blob.len() as u32, blob.as_ptr() as *mut u8, &mut handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(TssRsaKey { context: self, handle })
+    }
+
+    /// Queries a TSS capability, returning the response undecoded.
+    pub fn get_capability(&self, cap_area: TssFlag, sub_cap: &[u8]) -> Result<Vec<u8>, TssError> {
+        let mut resp_data_length = 0;
+        let mut resp_data_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_Context_GetCapability(self.handle, cap_area, sub_cap.len() as u32, sub_cap.as_ptr() as *mut u8, &mut resp_data_length, &mut resp_data_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let resp_data = copy_raw_ptr_to_vec(resp_data_ptr, resp_data_length as usize);
+        unsafe {
+            Tspi_Context_FreeMemory(self.handle, resp_data_ptr);
+        }
+        Ok(resp_data)
+    }
+
+    /// Returns whether the TSP supports `algorithm`, one of the `TSS_ALG_*`
+    /// values.
+    pub fn is_tsp_algorithm_supported(&self, algorithm: TssFlag) -> Result<bool, TssError> {
+        decode_cap_bool(&self.get_capability(TSS_TSPCAP_ALG, &algorithm.to_ne_bytes())?)
+    }
+
+    /// Returns whether the TCS supports `algorithm`, one of the `TSS_ALG_*`
+    /// values.
+    pub fn is_tcs_algorithm_supported(&self, algorithm: TssFlag) -> Result<bool, TssError> {
+        decode_cap_bool(&self.get_capability(TSS_TCSCAP_ALG, &algorithm.to_ne_bytes())?)
+    }
+
+    /// Describes the TSS service provider this context runs in.
+    pub fn get_tsp_info(&self) -> Result<SoftwareStackInfo, TssError> {
+        Ok(SoftwareStackInfo {
+            version: decode_cap_version(&self.get_capability(TSS_TSPCAP_VERSION, &[])?)?,
+            persistent_storage: decode_cap_bool(&self.get_capability(TSS_TSPCAP_PERSSTORAGE, &[])?)?,
+            manufacturer_id: decode_cap_uint32(&self.get_capability(TSS_TSPCAP_MANUFACTURER, &TSS_TSPCAP_PROP_MANUFACTURER_ID.to_ne_bytes())?)?,
+            manufacturer: capability::decode_cap_string(&self.get_capability(TSS_TSPCAP_MANUFACTURER, &TSS_TSPCAP_PROP_MANUFACTURER_STR.to_ne_bytes())?)
+        })
+    }
+
+    /// Describes the TSS core services (tcsd) this context is connected to.
+    pub fn get_tcs_info(&self) -> Result<SoftwareStackInfo, TssError> {
+        Ok(SoftwareStackInfo {
+            version: decode_cap_version(&self.get_capability(TSS_TCSCAP_VERSION, &[])?)?,
+            persistent_storage: decode_cap_bool(&self.get_capability(TSS_TCSCAP_PERSSTORAGE, &[])?)?,
+            manufacturer_id: decode_cap_uint32(&self.get_capability(TSS_TCSCAP_MANUFACTURER, &TSS_TCSCAP_PROP_MANUFACTURER_ID.to_ne_bytes())?)?,
+            manufacturer: capability::decode_cap_string(&self.get_capability(TSS_TCSCAP_MANUFACTURER, &TSS_TCSCAP_PROP_MANUFACTURER_STR.to_ne_bytes())?)
+        })
+    }
+
     // TODO: DRY creating objects, probably use try!
 
     // TODO: make this signature shorter? give default values?
@@ -327,6 +686,19 @@ impl TssContext {
         Ok(TssPolicy { context: self, handle: handle })
     }
 
+    /// Creates a key object with the TSS's SRK parameters, for use with
+    /// `TssTPM::take_ownership`.
+    pub fn create_srk_object(&self) -> Result<TssRsaKey<'_>, TssError> {
+        let mut handle = 0;
+        let result = unsafe {
+            Tspi_Context_CreateObject(self.handle, TSS_OBJECT_TYPE_RSAKEY, TSS_KEY_TSP_SRK | TSS_KEY_AUTHORIZATION, &mut handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(TssRsaKey { context: self, handle })
+    }
+
     pub fn create_pcr_composite_info(&self) -> Result<TssPCRCompositeInfo, TssError> {
         let mut handle = 0;
         let result = unsafe {
@@ -357,6 +729,33 @@ impl TssContext {
         }
         Ok(TssPCRCompositeInfoShort { context: self, handle: handle })
     }
+
+    /// Creates an NV store object for the NV area at `index`. Set the size
+    /// and permissions before `TssNvStore::define_space`; existing areas
+    /// only need the index.
+    pub fn create_nv_store(&self, index: NvIndex) -> Result<TssNvStore<'_>, TssError> {
+        let mut handle = 0;
+        let result = unsafe {
+            Tspi_Context_CreateObject(self.handle, TSS_OBJECT_TYPE_NV, 0, &mut handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let nv_store = TssNvStore { context: self, handle, chunk_size: Cell::new(None) };
+        nv_store.set_attrib_uint32(TSS_TSPATTRIB_NV_INDEX, 0, index.value())?;
+        Ok(nv_store)
+    }
+
+    pub fn create_enc_data(&self, enc_data_type: TssEncDataType) -> Result<TssEncData<'_>, TssError> {
+        let mut handle = 0;
+        let result = unsafe {
+            Tspi_Context_CreateObject(self.handle, TSS_OBJECT_TYPE_ENCDATA, enc_data_type as u32, &mut handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(TssEncData { context: self, handle })
+    }
 }
 
 impl Drop for TssContext {
@@ -369,18 +768,192 @@ impl Drop for TssContext {
 }
 
 impl<'context> TssTPM<'context> {
-    // TODO: UNTESTED
-    pub fn quote(&self, ident_key: &TssRsaKey, pcr_composite: &TssPCRCompositeInfo, external_data: &[u8; 20]) -> Result<TssValidation, TssError> {
-        let mut validation_data = TSS_VALIDATION { versionInfo: TSS_VERSION { bMajor: 0, bMinor: 0, bRevMajor: 0, bRevMinor: 0 }, ulExternalDataLength: 20, rgbExternalData: external_data.as_ptr() as *mut u8, ulDataLength: 0, rgbData: 0 as *mut u8, ulValidationDataLength: 0, rgbValidationData: 0 as *mut u8 };
+    /// Queries a TPM capability, returning the response undecoded.
+    pub fn get_capability(&self, cap_area: TssFlag, sub_cap: &[u8]) -> Result<Vec<u8>, TssError> {
+        let mut resp_data_length = 0;
+        let mut resp_data_ptr = ptr::null_mut();
         let result = unsafe {
-            Tspi_TPM_Quote(self.handle, ident_key.handle, pcr_composite.handle, &mut validation_data)
+            Tspi_TPM_GetCapability(self.handle, cap_area, sub_cap.len() as u32, sub_cap.as_ptr() as *mut u8, &mut resp_data_length, &mut resp_data_ptr)
         };
         if result != TSS_SUCCESS {
-            return Err(TssError { result: result });
+            return Err(TssError { result });
+        }
+        let resp_data = copy_raw_ptr_to_vec(resp_data_ptr, resp_data_length as usize);
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, resp_data_ptr);
+        }
+        Ok(resp_data)
+    }
+
+    /// Returns a `TSS_TPMCAP_PROPERTY` value, where `property` is one of the
+    /// `TSS_TPMCAP_PROP_*` values that answer with a `UINT32`.
+    pub fn get_property(&self, property: TssFlag) -> Result<u32, TssError> {
+        decode_cap_uint32(&self.get_capability(TSS_TPMCAP_PROPERTY, &property.to_ne_bytes())?)
+    }
+
+    pub fn get_version(&self) -> Result<TpmVersion, TssError> {
+        decode_cap_version(&self.get_capability(TSS_TPMCAP_VERSION, &[])?)
+    }
+
+    /// Returns the full version information of a 1.2 TPM. 1.1 TPMs only
+    /// support `get_version`.
+    pub fn get_version_info(&self) -> Result<CapVersionInfo, TssError> {
+        CapVersionInfo::parse(&self.get_capability(TSS_TPMCAP_VERSION_VAL, &[])?).map_err(|_| internal_error())
+    }
+
+    pub fn get_pcr_count(&self) -> Result<u32, TssError> {
+        self.get_property(TSS_TPMCAP_PROP_PCR)
+    }
+
+    /// Returns the number of keys the TPM can hold loaded at once.
+    pub fn get_key_slots(&self) -> Result<u32, TssError> {
+        self.get_property(TSS_TPMCAP_PROP_SLOTS)
+    }
+
+    /// Returns the TCG vendor ID of the TPM manufacturer, e.g. `*b"IFX\0"`.
+    pub fn get_manufacturer(&self) -> Result<[u8; 4], TssError> {
+        Ok(self.get_property(TSS_TPMCAP_PROP_MANUFACTURER)?.to_be_bytes())
+    }
+
+    /// Returns whether the TPM implements the command with ordinal `ordinal`.
+    pub fn is_ordinal_supported(&self, ordinal: u32) -> Result<bool, TssError> {
+        decode_cap_bool(&self.get_capability(TSS_TPMCAP_ORD, &ordinal.to_ne_bytes())?)
+    }
+
+    /// Returns whether the TPM supports `algorithm`, one of the `TSS_ALG_*`
+    /// values.
+    pub fn is_algorithm_supported(&self, algorithm: TssFlag) -> Result<bool, TssError> {
+        decode_cap_bool(&self.get_capability(TSS_TPMCAP_ALG, &algorithm.to_ne_bytes())?)
+    }
+
+    /// Returns whether the TPM accepts PCR selections `size_of_select` bytes
+    /// long, e.g. 3 to address 24 PCRs.
+    ///
+    /// TPM 1.2 has no capability describing the attributes of individual
+    /// PCRs, such as which are resettable; those are fixed by the platform
+    /// specification.
+    pub fn is_select_size_supported(&self, size_of_select: u16) -> Result<bool, TssError> {
+        // A TPM_SELECT_SIZE for the 1.2 specification, in host byte order
+        let mut select_size = vec![1, 2];
+        select_size.extend_from_slice(&size_of_select.to_ne_bytes());
+        decode_cap_bool(&self.get_capability(TSS_TPMCAP_SELECT_SIZE, &select_size)?)
+    }
+
+    /// Returns the TPM's permanent flags. Requires owner authorization.
+    pub fn permanent_flags(&self) -> Result<PermanentFlags, TssError> {
+        let (non_volatile_flags, _) = self.get_flag_bitmaps()?;
+        Ok(PermanentFlags::from_bitmap(non_volatile_flags))
+    }
+
+    /// Returns the TPM's ST_CLEAR flags. Requires owner authorization.
+    pub fn stclear_flags(&self) -> Result<StClearFlags, TssError> {
+        let (_, volatile_flags) = self.get_flag_bitmaps()?;
+        Ok(StClearFlags::from_bitmap(volatile_flags))
+    }
+
+    // TrouSerS answers TSS_TPMCAP_FLAG with TPM_GetCapabilityOwner, packing
+    // the non-volatile and volatile bitmaps as big-endian UINT32s
+    fn get_flag_bitmaps(&self) -> Result<(u32, u32), TssError> {
+        let data = self.get_capability(TSS_TPMCAP_FLAG, &[])?;
+        if data.len() != 8 {
+            return Err(internal_error());
+        }
+        let non_volatile_flags = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let volatile_flags = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        Ok((non_volatile_flags, volatile_flags))
+    }
+
+    pub fn get_status(&self, flag: TpmStatusFlag) -> Result<bool, TssError> {
+        let mut state = 0;
+        let result = unsafe {
+            Tspi_TPM_GetStatus(self.handle, flag as u32, &mut state)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(state != 0)
+    }
+
+    /// Sets `flag` to `state`. See `TpmStatusFlag` for the authorization
+    /// each flag needs; on failure the error names it.
+    pub fn set_status(&self, flag: TpmStatusFlag, state: bool) -> Result<(), TpmStatusError> {
+        let required = flag.set_authorization();
+        if required == TpmStatusAuth::ReadOnly {
+            return Err(TpmStatusError { flag, required, error: None });
+        }
+        let result = unsafe {
+            Tspi_TPM_SetStatus(self.handle, flag as u32, state as TSS_BOOL)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TpmStatusError { flag, required, error: Some(TssError { result }) });
+        }
+        Ok(())
+    }
+
+    /// Takes ownership of the TPM, creating the storage root key in `srk`.
+    ///
+    /// The owner secret comes from the policy assigned to the TPM object and
+    /// the SRK secret from the usage policy assigned to `srk`; set both with
+    /// `TssPolicy::set_secret` and `TssPolicy::assign_to_object` first. If
+    /// `ek_pubkey` is `None` the TSS reads the endorsement key itself.
+    ///
+    /// Returns the SRK, which now refers to the key held by the TPM.
+    pub fn take_ownership(&self, srk: &TssRsaKey<'context>, ek_pubkey: Option<&TssRsaKey>) -> Result<TssRsaKey<'context>, TssError> {
+        let ek_handle = ek_pubkey.map_or(0, |key| key.handle);
+        let result = unsafe {
+            Tspi_TPM_TakeOwnership(self.handle, srk.handle, ek_handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(TssRsaKey { context: srk.context, handle: srk.handle })
+    }
+
+    /// Removes the TPM owner, invalidating the SRK and every key under it.
+    ///
+    /// Without `force` this is `TPM_OwnerClear` and needs owner
+    /// authorization through the TPM object's policy. With `force` it is
+    /// `TPM_ForceClear`, which needs physical presence instead.
+    pub fn clear_owner(&self, force: bool) -> Result<(), TssError> {
+        let result = unsafe {
+            Tspi_TPM_ClearOwner(self.handle, force as TSS_BOOL)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(())
+    }
+
+    /// Reads the public endorsement key. With `owner_authorized` the read
+    /// uses owner authorization, which works after `DisablePubekRead`;
+    /// otherwise the TSS checks the TPM's response with its own nonce.
+    pub fn get_pub_endorsement_key(&self, owner_authorized: bool) -> Result<TssRsaKey<'context>, TssError> {
+        let mut handle = 0;
+        let result = unsafe {
+            Tspi_TPM_GetPubEndorsementKey(self.handle, owner_authorized as TSS_BOOL, ptr::null_mut(), &mut handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(TssRsaKey { context: self.context, handle })
+    }
+
+    /// Reads the public endorsement key without owner authorization, using
+    /// `anti_replay` as the nonce. The returned validation holds the
+    /// `TPM_PUBKEY` and the TPM's checksum; check them with
+    /// `ek::verify_pub_endorsement_key`.
+    pub fn get_pub_endorsement_key_with_nonce(&self, anti_replay: &[u8; 20]) -> Result<(TssRsaKey<'context>, TssValidation), TssError> {
+        let mut validation_data = TSS_VALIDATION { versionInfo: TSS_VERSION::default(), ulExternalDataLength: 20, rgbExternalData: anti_replay.as_ptr() as *mut u8, ulDataLength: 0, rgbData: ptr::null_mut(), ulValidationDataLength: 0, rgbValidationData: ptr::null_mut() };
+        let mut handle = 0;
+        let result = unsafe {
+            Tspi_TPM_GetPubEndorsementKey(self.handle, 0, &mut validation_data, &mut handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
         }
         let validation_result = TssValidation {
-            version_info: validation_data.versionInfo.clone(),
-            external_data: copy_raw_ptr_to_vec(external_data as *const u8, external_data.len()),
+            version_info: validation_data.versionInfo,
+            external_data: anti_replay.to_vec(),
             data: copy_raw_ptr_to_vec(validation_data.rgbData, validation_data.ulDataLength as usize),
             validation_data: copy_raw_ptr_to_vec(validation_data.rgbValidationData, validation_data.ulValidationDataLength as usize)
         };
@@ -388,79 +961,860 @@ impl<'context> TssTPM<'context> {
             Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbData);
             Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbValidationData);
         }
-        Ok(validation_result)
+        Ok((TssRsaKey { context: self.context, handle }, validation_result))
     }
 
-    pub fn pcr_read(&self, pcr_index: u32) -> Result<Vec<u8>, TssError> {
-        let mut pcr_value_length = 0;
-        let mut pcr_value_ptr = 0 as *mut u8;
+    /// Creates the endorsement key from the parameters of `ek`, which
+    /// afterwards holds the public key. Fails once the TPM has an EK.
+    pub fn create_endorsement_key(&self, ek: &TssRsaKey) -> Result<(), TssError> {
         let result = unsafe {
-            Tspi_TPM_PcrRead(self.handle, pcr_index, &mut pcr_value_length, &mut pcr_value_ptr)
+            Tspi_TPM_CreateEndorsementKey(self.handle, ek.handle, ptr::null_mut())
         };
         if result != TSS_SUCCESS {
-            return Err(TssError { result: result });
+            return Err(TssError { result });
         }
-        let mut vec = Vec::new();
+        Ok(())
+    }
+
+    /// Creates a revocable endorsement key. If `reset_secret` is `None` the
+    /// TPM generates the reset authorization; either way the secret needed
+    /// by `revoke_endorsement_key` is returned.
+    pub fn create_revocable_endorsement_key(&self, ek: &TssRsaKey, reset_secret: Option<&EkResetSecret>) -> Result<EkResetSecret, TssError> {
+        let mut reset_data_length = 0;
+        let mut reset_data_ptr = ptr::null_mut();
+        if let Some(secret) = reset_secret {
+            reset_data_length = secret.as_bytes().len() as u32;
+            reset_data_ptr = secret.as_bytes().as_ptr() as *mut u8;
+        }
+        let result = unsafe {
+            Tspi_TPM_CreateRevocableEndorsementKey(self.handle, ek.handle, ptr::null_mut(), &mut reset_data_length, &mut reset_data_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        if let Some(secret) = reset_secret {
+            return Ok(EkResetSecret::from_bytes(*secret.as_bytes()));
+        }
+        if reset_data_ptr.is_null() {
+            return Err(internal_error());
+        }
+        let mut secret = [0; 20];
         unsafe {
-            for i in 0..pcr_value_length {
-                // TODO: Is this isize cast safe?
-                vec.push(*pcr_value_ptr.offset(i as isize));
+            let reset_data = slice::from_raw_parts_mut(reset_data_ptr, reset_data_length as usize);
+            if reset_data.len() == secret.len() {
+                secret.copy_from_slice(reset_data);
             }
-            Tspi_Context_FreeMemory(self.context.handle, pcr_value_ptr);
+            memory::wipe(reset_data);
+            Tspi_Context_FreeMemory(self.context.handle, reset_data_ptr);
         }
-        Ok(vec)
+        if reset_data_length as usize != secret.len() {
+            return Err(internal_error());
+        }
+        let reset_secret = EkResetSecret::from_bytes(secret);
+        memory::wipe(&mut secret);
+        Ok(reset_secret)
     }
 
-    // TODO: events
-    pub fn pcr_extend(&self, pcr_index: u32, data: &[u8]) -> Result<Vec<u8>, TssError> {
-        let mut pcr_value_length = 0;
-        let mut pcr_value_ptr = 0 as *mut u8;
+    /// Revokes a revocable endorsement key. Like `ClearOwner` with `force`,
+    /// this needs physical presence.
+    pub fn revoke_endorsement_key(&self, reset_secret: &EkResetSecret) -> Result<(), TssError> {
+        let secret = reset_secret.as_bytes();
         let result = unsafe {
-            // TODO: Is this u32 cast safe?
-            Tspi_TPM_PcrExtend(self.handle, pcr_index, data.len() as u32, data.as_ptr() as *mut u8, 0 as *mut Struct_tdTSS_PCR_EVENT, &mut pcr_value_length, &mut pcr_value_ptr)
+            Tspi_TPM_RevokeEndorsementKey(self.handle, secret.len() as u32, secret.as_ptr() as *mut u8)
         };
         if result != TSS_SUCCESS {
-            return Err(TssError { result: result });
+            return Err(TssError { result });
         }
-        // TODO: DRY with above
-        let mut vec = Vec::new();
+        Ok(())
+    }
+
+    /// Reads the endorsement key certificate from `TPM_NV_INDEX_EKCERT` and
+    /// returns it as DER. Some TPMs only allow the owner to read the index,
+    /// in which case the TPM object needs an owner policy.
+    pub fn get_ek_certificate(&self) -> Result<Vec<u8>, TssError> {
+        let nv_store = self.context.create_nv_store(NvIndex::EK_CERT)?;
+        let mut nv_data = nv_store.read(0, ekcert::STORED_CERT_HEADER_LENGTH)?;
+        let cert_size = ekcert::stored_cert_size(&nv_data).map_err(|_| internal_error())?;
+        nv_data.extend_from_slice(&nv_store.read(ekcert::STORED_CERT_HEADER_LENGTH as u32, cert_size)?);
+        ekcert::parse_stored_cert(&nv_data).map_err(|_| internal_error())
+    }
+
+    /// Creates `identity_key` under `srk` and returns a `TPM_IDENTITY_REQ`
+    /// for the privacy CA whose public key is loaded in `ca_pubkey` (see
+    /// `TssRsaKey::set_pub_key`). `sym_algorithm` is one of the `TSS_ALG_*`
+    /// symmetric algorithms, used to encrypt the identity proof.
+    ///
+    /// Needs owner authorization through the TPM object's policy, and the
+    /// SRK's usage secret. Parse the result with `IdentityRequest::parse`.
+    ///
+    /// `identity_key` should come from `TssContext::create_rsakey` with
+    /// `TssKeyType::Identity`. Once created it is not loaded; call
+    /// `TssRsaKey::load_key` with `srk` before using it, and save
+    /// `TssRsaKey::get_key_blob` to load it again later with
+    /// `TssContext::load_key_by_blob`.
+    pub fn collate_identity_request(&self, srk: &TssRsaKey, ca_pubkey: &TssRsaKey, label: &[u8], identity_key: &TssRsaKey, sym_algorithm: TssFlag) -> Result<Vec<u8>, TssError> {
+        let mut request_length = 0;
+        let mut request_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_CollateIdentityRequest(self.handle, srk.handle, ca_pubkey.handle, label.len() as u32, label.as_ptr() as *mut u8, identity_key.handle, sym_algorithm, &mut request_length, &mut request_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let request = copy_raw_ptr_to_vec(request_ptr, request_length as usize);
         unsafe {
-            for i in 0..pcr_value_length {
-                // TODO: Is this isize cast safe?
-                vec.push(*pcr_value_ptr.offset(i as isize));
-            }
-            Tspi_Context_FreeMemory(self.context.handle, pcr_value_ptr);
+            Tspi_Context_FreeMemory(self.context.handle, request_ptr);
         }
-        Ok(vec)
+        Ok(request)
     }
 
-    pub fn pcr_reset(&self, pcr_composite: &TcpaPcrInfoAny) -> Result<(), TssError> {
+    /// Decrypts the privacy CA's response to an identity request with the
+    /// endorsement key, returning the credential issued for `identity_key`.
+    ///
+    /// `asym_ca_contents` is the `TPM_EK_BLOB` or `TPM_ASYM_CA_CONTENTS`
+    /// encrypted to the EK and `sym_ca_attestation` the
+    /// `TPM_SYM_CA_ATTESTATION`. Needs owner authorization and the identity
+    /// key's usage secret.
+    pub fn activate_identity(&self, identity_key: &TssRsaKey, asym_ca_contents: &[u8], sym_ca_attestation: &[u8]) -> Result<Vec<u8>, TssError> {
+        let mut credential_length = 0;
+        let mut credential_ptr = ptr::null_mut();
         let result = unsafe {
-            Tspi_TPM_PcrReset(self.handle, pcr_composite.get_handle())
+            Tspi_TPM_ActivateIdentity(self.handle, identity_key.handle, asym_ca_contents.len() as u32, asym_ca_contents.as_ptr() as *mut u8, sym_ca_attestation.len() as u32, sym_ca_attestation.as_ptr() as *mut u8, &mut credential_length, &mut credential_ptr)
         };
         if result != TSS_SUCCESS {
-            return Err(TssError { result: result });
+            return Err(TssError { result });
+        }
+        let credential = copy_raw_ptr_to_vec(credential_ptr, credential_length as usize);
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, credential_ptr);
+        }
+        Ok(credential)
+    }
+
+    /// Returns `length` bytes from the TPM's random number generator.
+    pub fn get_random(&self, length: usize) -> Result<Vec<u8>, TssError> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+        let mut random_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_GetRandom(self.handle, length as u32, &mut random_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        // Freeing a null pointer would free all of the context's memory
+        if random_ptr.is_null() {
+            return Err(internal_error());
+        }
+        let random = copy_raw_ptr_to_vec(random_ptr, length);
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, random_ptr);
+        }
+        Ok(random)
+    }
+
+    /// Mixes `entropy` into the TPM's random number generator state, in
+    /// pieces of the most `TPM_StirRandom` accepts.
+    pub fn stir_random(&self, entropy: &[u8]) -> Result<(), TssError> {
+        for chunk in entropy.chunks(STIR_RANDOM_MAX) {
+            let result = unsafe {
+                Tspi_TPM_StirRandom(self.handle, chunk.len() as u32, chunk.as_ptr() as *mut u8)
+            };
+            if result != TSS_SUCCESS {
+                return Err(TssError { result });
+            }
         }
         Ok(())
     }
-}
 
-impl<'context> TssPolicy<'context> {
-    //pub fn set_secret(mode: TssSecretMode, secret_length: u32, secret: &[u8]) -> Result<(), TssError> {
-        // TODO
-    //}
-}
+    /// Lists the indices of the defined NV areas.
+    pub fn nv_indices(&self) -> Result<Vec<NvIndex>, TssError> {
+        let data = self.get_capability(TSS_TPMCAP_NV_LIST, &[])?;
+        nv::parse_nv_list(&data).map_err(|_| internal_error())
+    }
 
-impl<'c> TssObject for TssRsaKey<'c> {
-    fn get_handle(&self) -> TssHObject { self.handle }
-    fn set_attrib_uint32(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib: u32) -> Result<(), TssError> {
-        set_attrib_uint32_impl(self, attrib_flag, sub_flag, attrib)
+    /// Describes the NV area at `index`.
+    pub fn nv_info(&self, index: NvIndex) -> Result<NvDataPublic, TssError> {
+        let data = self.get_capability(TSS_TPMCAP_NV_INDEX, &index.value().to_ne_bytes())?;
+        NvDataPublic::parse(&data).map_err(|_| internal_error())
+    }
+
+    /// Describes every defined NV area.
+    pub fn nv_list(&self) -> Result<Vec<NvDataPublic>, TssError> {
+        self.nv_indices()?.into_iter().map(|index| self.nv_info(index)).collect()
+    }
+
+    /// Reads the TPM's active monotonic counter. Fails if no counter has
+    /// been incremented since the last reboot.
+    pub fn read_counter(&self) -> Result<u32, TssError> {
+        let mut counter_value = 0;
+        let result = unsafe {
+            Tspi_TPM_ReadCounter(self.handle, &mut counter_value)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(counter_value)
+    }
+
+    /// Reads the audit digest and the TPM's audit counter, without signing
+    /// them or closing the audit session.
+    pub fn get_audit_digest(&self) -> Result<(Vec<u8>, CounterValue), TssError> {
+        let mut audit_digest_length = 0;
+        let mut audit_digest_ptr = ptr::null_mut();
+        let mut counter_value = TPM_COUNTER_VALUE::default();
+        let mut ordinal_count = 0;
+        let mut ordinals_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_GetAuditDigest(self.handle, 0, 0, &mut audit_digest_length, &mut audit_digest_ptr,
+                                    &mut counter_value, ptr::null_mut(), &mut ordinal_count, &mut ordinals_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let audit_digest = copy_raw_ptr_to_vec(audit_digest_ptr, audit_digest_length as usize);
+        // Freeing a null pointer would free all of the context's memory
+        unsafe {
+            if !audit_digest_ptr.is_null() {
+                Tspi_Context_FreeMemory(self.context.handle, audit_digest_ptr);
+            }
+            if !ordinals_ptr.is_null() {
+                Tspi_Context_FreeMemory(self.context.handle, ordinals_ptr as *mut u8);
+            }
+        }
+        Ok((audit_digest, CounterValue::from(counter_value)))
+    }
+
+    /// Returns the ID of the counter active since the last reboot, if any.
+    pub fn get_active_counter_id(&self) -> Result<Option<u32>, TssError> {
+        match self.get_property(TSS_TPMCAP_PROP_ACTIVECOUNTER)? {
+            counter::TPM_COUNT_ID_NULL => Ok(None),
+            counter_id => Ok(Some(counter_id))
+        }
+    }
+
+    /// Reads the TPM's tick counter.
+    pub fn current_ticks(&self) -> Result<CurrentTicks, TssError> {
+        let mut ticks = TPM_CURRENT_TICKS::default();
+        let result = unsafe {
+            Tspi_TPM_ReadCurrentTicks(self.handle, &mut ticks)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(CurrentTicks::from(ticks))
+    }
+
+    /// Returns the number of DIR registers, 1 on every TPM 1.2.
+    pub fn get_dir_count(&self) -> Result<u32, TssError> {
+        self.get_property(TSS_TPMCAP_PROP_DIR)
+    }
+
+    /// Reads a DIR register. Needs no authorization.
+    pub fn dir_read(&self, dir_index: u32) -> Result<[u8; 20], TssError> {
+        self.check_dir_index(dir_index)?;
+        let mut dir_data_length = 0;
+        let mut dir_data_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_DirRead(self.handle, dir_index, &mut dir_data_length, &mut dir_data_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let dir_data = copy_raw_ptr_to_vec(dir_data_ptr, dir_data_length as usize);
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, dir_data_ptr);
+        }
+        if dir_data.len() != 20 {
+            return Err(internal_error());
+        }
+        let mut value = [0; 20];
+        value.copy_from_slice(&dir_data);
+        Ok(value)
+    }
+
+    /// Writes a DIR register. Needs owner authorization through the TPM
+    /// object's policy.
+    pub fn dir_write(&self, dir_index: u32, value: &[u8; 20]) -> Result<(), TssError> {
+        self.check_dir_index(dir_index)?;
+        let result = unsafe {
+            Tspi_TPM_DirWrite(self.handle, dir_index, value.len() as u32, value.as_ptr() as *mut u8)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(())
+    }
+
+    fn check_dir_index(&self, dir_index: u32) -> Result<(), TssError> {
+        if dir_index >= self.get_dir_count()? {
+            return Err(bad_parameter());
+        }
+        Ok(())
+    }
+
+    /// Runs all of the TPM's self-tests. A failed test is reported in the
+    /// result rather than as an error, along with the vendor's test output.
+    pub fn self_test_full(&self) -> Result<SelfTestResult, TssError> {
+        let result = unsafe {
+            Tspi_TPM_SelfTestFull(self.handle)
+        };
+        if result != TSS_SUCCESS && result != self_test::TPM_E_FAILEDSELFTEST {
+            return Err(TssError { result });
+        }
+        Ok(SelfTestResult { passed: result == TSS_SUCCESS, data: self.get_test_result()? })
+    }
+
+    /// Returns the vendor-specific results of the last self-test. This
+    /// still works while the TPM is in failure mode.
+    pub fn get_test_result(&self) -> Result<Vec<u8>, TssError> {
+        let mut test_result_length = 0;
+        let mut test_result_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_GetTestResult(self.handle, &mut test_result_length, &mut test_result_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let test_result = copy_raw_ptr_to_vec(test_result_ptr, test_result_length as usize);
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, test_result_ptr);
+        }
+        Ok(test_result)
+    }
+
+    /// Runs the self-tests and has `key` sign the result with
+    /// `anti_replay` as the nonce. Fails if any test fails. Use
+    /// `CertifiedSelfTest::from_validation` to verify the result.
+    pub fn certify_self_test(&self, key: &TssRsaKey, anti_replay: &[u8; 20]) -> Result<TssValidation, TssError> {
+        let mut validation_data = TSS_VALIDATION { versionInfo: TSS_VERSION::default(), ulExternalDataLength: 20, rgbExternalData: anti_replay.as_ptr() as *mut u8, ulDataLength: 0, rgbData: ptr::null_mut(), ulValidationDataLength: 0, rgbValidationData: ptr::null_mut() };
+        let result = unsafe {
+            Tspi_TPM_CertifySelfTest(self.handle, key.handle, &mut validation_data)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let validation_result = TssValidation {
+            version_info: validation_data.versionInfo,
+            external_data: anti_replay.to_vec(),
+            data: copy_raw_ptr_to_vec(validation_data.rgbData, validation_data.ulDataLength as usize),
+            validation_data: copy_raw_ptr_to_vec(validation_data.rgbValidationData, validation_data.ulValidationDataLength as usize)
+        };
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbData);
+            Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbValidationData);
+        }
+        Ok(validation_result)
+    }
+
+    /// Quotes the PCRs selected in `pcr_composite` with `ident_key`. Use
+    /// `Quote::from_validation` to parse and verify the result.
+    pub fn quote(&self, ident_key: &TssRsaKey, pcr_composite: &TssPCRCompositeInfo, external_data: &[u8; 20]) -> Result<TssValidation, TssError> {
+        let mut validation_data = TSS_VALIDATION { versionInfo: TSS_VERSION::default(), ulExternalDataLength: 20, rgbExternalData: external_data.as_ptr() as *mut u8, ulDataLength: 0, rgbData: ptr::null_mut(), ulValidationDataLength: 0, rgbValidationData: ptr::null_mut() };
+        let result = unsafe {
+            Tspi_TPM_Quote(self.handle, ident_key.handle, pcr_composite.handle, &mut validation_data)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let validation_result = TssValidation {
+            version_info: validation_data.versionInfo,
+            external_data: external_data.to_vec(),
+            data: copy_raw_ptr_to_vec(validation_data.rgbData, validation_data.ulDataLength as usize),
+            validation_data: copy_raw_ptr_to_vec(validation_data.rgbValidationData, validation_data.ulValidationDataLength as usize)
+        };
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbData);
+            Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbValidationData);
+        }
+        Ok(validation_result)
+    }
+
+    /// Quotes the PCRs selected for release in `pcr_composite` with
+    /// `ident_key`. If `add_version` is set, the TPM's
+    /// `TPM_CAP_VERSION_INFO` is appended to the signed data. Use
+    /// `Quote2::from_validation` to parse and verify the result.
+    pub fn quote2(&self, ident_key: &TssRsaKey, pcr_composite: &TssPCRCompositeInfoShort, external_data: &[u8; 20], add_version: bool) -> Result<TssValidation, TssError> {
+        let mut validation_data = TSS_VALIDATION { versionInfo: TSS_VERSION::default(), ulExternalDataLength: 20, rgbExternalData: external_data.as_ptr() as *mut u8, ulDataLength: 0, rgbData: ptr::null_mut(), ulValidationDataLength: 0, rgbValidationData: ptr::null_mut() };
+        let mut version_info_length = 0;
+        let mut version_info_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_Quote2(self.handle, ident_key.handle, add_version as TSS_BOOL, pcr_composite.handle, &mut validation_data, &mut version_info_length, &mut version_info_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        // The version info is also appended to the signed data, which is
+        // where Quote2 reads it from
+        let validation_result = TssValidation {
+            version_info: validation_data.versionInfo,
+            external_data: external_data.to_vec(),
+            data: copy_raw_ptr_to_vec(validation_data.rgbData, validation_data.ulDataLength as usize),
+            validation_data: copy_raw_ptr_to_vec(validation_data.rgbValidationData, validation_data.ulValidationDataLength as usize)
+        };
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbData);
+            Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbValidationData);
+            if !version_info_ptr.is_null() {
+                Tspi_Context_FreeMemory(self.context.handle, version_info_ptr);
+            }
+        }
+        Ok(validation_result)
+    }
+
+    pub fn pcr_read(&self, pcr_index: u32) -> Result<Vec<u8>, TssError> {
+        let mut pcr_value_length = 0;
+        let mut pcr_value_ptr = 0 as *mut u8;
+        let result = unsafe {
+            Tspi_TPM_PcrRead(self.handle, pcr_index, &mut pcr_value_length, &mut pcr_value_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result: result });
+        }
+        let mut vec = Vec::new();
+        unsafe {
+            for i in 0..pcr_value_length {
+                // TODO: Is this isize cast safe?
+                vec.push(*pcr_value_ptr.offset(i as isize));
+            }
+            Tspi_Context_FreeMemory(self.context.handle, pcr_value_ptr);
+        }
+        Ok(vec)
+    }
+
+    pub fn pcr_extend(&self, pcr_index: u32, data: &[u8]) -> Result<Vec<u8>, TssError> {
+        let mut pcr_value_length = 0;
+        let mut pcr_value_ptr = 0 as *mut u8;
+        let result = unsafe {
+            // TODO: Is this u32 cast safe?
+            Tspi_TPM_PcrExtend(self.handle, pcr_index, data.len() as u32, data.as_ptr() as *mut u8, 0 as *mut Struct_tdTSS_PCR_EVENT, &mut pcr_value_length, &mut pcr_value_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result: result });
+        }
+        // TODO: DRY with above
+        let mut vec = Vec::new();
+        unsafe {
+            for i in 0..pcr_value_length {
+                // TODO: Is this isize cast safe?
+                vec.push(*pcr_value_ptr.offset(i as isize));
+            }
+            Tspi_Context_FreeMemory(self.context.handle, pcr_value_ptr);
+        }
+        Ok(vec)
+    }
+
+    /// Extends `data` into the PCR named by `event.pcr_index` and records
+    /// `event` in the TSS event log.
+    ///
+    /// When an event is supplied the TSS extends the SHA1 of the PCR index,
+    /// `data`, the event type and the event data rather than `data` itself.
+    /// Returns the new PCR value along with the event as it was logged.
+    pub fn extend_with_event(&self, data: &[u8], event: &PcrEvent) -> Result<(Vec<u8>, PcrEvent), TssError> {
+        let mut raw_event = TSS_PCR_EVENT {
+            versionInfo: event.version_info,
+            ulPcrIndex: event.pcr_index,
+            eventType: event.event_type.to_u32(),
+            ulPcrValueLength: 0,
+            rgbPcrValue: ptr::null_mut(),
+            ulEventLength: event.event.len() as u32,
+            rgbEvent: event.event.as_ptr() as *mut u8
+        };
+        let mut pcr_value_length = 0;
+        let mut pcr_value_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_PcrExtend(self.handle, event.pcr_index, data.len() as u32, data.as_ptr() as *mut u8, &mut raw_event, &mut pcr_value_length, &mut pcr_value_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let pcr_value = copy_raw_ptr_to_vec(pcr_value_ptr, pcr_value_length as usize);
+        // Freeing a null pointer would free all of the context's memory
+        let logged_pcr_value = if raw_event.rgbPcrValue.is_null() {
+            Vec::new()
+        } else {
+            copy_raw_ptr_to_vec(raw_event.rgbPcrValue, raw_event.ulPcrValueLength as usize)
+        };
+        let logged_event = PcrEvent {
+            version_info: raw_event.versionInfo,
+            pcr_index: raw_event.ulPcrIndex,
+            event_type: event.event_type,
+            pcr_value: logged_pcr_value,
+            event: event.event.clone()
+        };
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, pcr_value_ptr);
+            if !raw_event.rgbPcrValue.is_null() {
+                Tspi_Context_FreeMemory(self.context.handle, raw_event.rgbPcrValue);
+            }
+        }
+        Ok((pcr_value, logged_event))
+    }
+
+    /// Returns the number of events logged against `pcr_index`.
+    pub fn get_event_count(&self, pcr_index: u32) -> Result<u32, TssError> {
+        let mut event_count = 0;
+        let result = unsafe {
+            Tspi_TPM_GetEvents(self.handle, pcr_index, 0, &mut event_count, ptr::null_mut())
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(event_count)
+    }
+
+    pub fn get_event(&self, pcr_index: u32, event_number: u32) -> Result<PcrEvent, TssError> {
+        let mut raw_event = TSS_PCR_EVENT::default();
+        let result = unsafe {
+            Tspi_TPM_GetEvent(self.handle, pcr_index, event_number, &mut raw_event)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let event = unsafe {
+            let event = PcrEvent::from_raw(&raw_event);
+            event_log::free_raw_event_data(&raw_event);
+            event
+        };
+        Ok(event)
+    }
+
+    /// Returns up to `event_count` events logged against `pcr_index`,
+    /// starting from `start_number`.
+    pub fn get_events(&self, pcr_index: u32, start_number: u32, event_count: u32) -> Result<Vec<PcrEvent>, TssError> {
+        let mut event_count = event_count;
+        let mut events_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_GetEvents(self.handle, pcr_index, start_number, &mut event_count, &mut events_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(self.take_raw_events(events_ptr, event_count))
+    }
+
+    /// Returns every event in the TSS event log, across all PCRs, in the
+    /// order they were logged.
+    pub fn get_event_log(&self) -> Result<Vec<PcrEvent>, TssError> {
+        let mut event_count = 0;
+        let mut events_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_TPM_GetEventLog(self.handle, &mut event_count, &mut events_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(self.take_raw_events(events_ptr, event_count))
+    }
+
+    // The event array belongs to the context, but the digest and event data
+    // inside each element are allocated separately by the RPC layer.
+    fn take_raw_events(&self, events_ptr: *mut TSS_PCR_EVENT, event_count: u32) -> Vec<PcrEvent> {
+        if events_ptr.is_null() {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        unsafe {
+            for raw_event in slice::from_raw_parts(events_ptr, event_count as usize) {
+                events.push(PcrEvent::from_raw(raw_event));
+                event_log::free_raw_event_data(raw_event);
+            }
+            Tspi_Context_FreeMemory(self.context.handle, events_ptr as *mut u8);
+        }
+        events
+    }
+
+    pub fn pcr_reset(&self, pcr_composite: &TcpaPcrInfoAny) -> Result<(), TssError> {
+        let result = unsafe {
+            Tspi_TPM_PcrReset(self.handle, pcr_composite.get_handle())
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result: result });
+        }
+        Ok(())
+    }
+}
+
+impl<'context> TssPolicy<'context> {
+    /// Sets the secret the policy authorizes with. For `TssSecretMode::Sha1`
+    /// `secret` is the 20 byte digest of the password; for
+    /// `TssSecretMode::Plain` it is the password itself.
+    pub fn set_secret(&self, mode: TssSecretMode, secret: &[u8]) -> Result<(), TssError> {
+        let result = unsafe {
+            Tspi_Policy_SetSecret(self.handle, mode as u32, secret.len() as u32, secret.as_ptr() as *mut u8)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(())
+    }
+
+    /// Makes this policy the one `object` authorizes with. Assigning a
+    /// policy to the TPM object supplies owner authorization.
+    pub fn assign_to_object(&self, object: &dyn TssObject) -> Result<(), TssError> {
+        let result = unsafe {
+            Tspi_Policy_AssignToObject(self.handle, object.get_handle())
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(())
+    }
+}
+
+impl<'c> TssRsaKey<'c> {
+    /// Returns the key's public half as an encoded `TPM_PUBKEY`, which
+    /// `RsaPublicKey::from_tpm_pubkey` can decode.
+    pub fn get_pub_key(&self) -> Result<Vec<u8>, TssError> {
+        let mut pub_key_length = 0;
+        let mut pub_key_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_Key_GetPubKey(self.handle, &mut pub_key_length, &mut pub_key_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let pub_key = copy_raw_ptr_to_vec(pub_key_ptr, pub_key_length as usize);
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, pub_key_ptr);
+        }
+        Ok(pub_key)
+    }
+
+    /// Has `certifier` sign a description of this key with `anti_replay`
+    /// as the nonce, proving that the key is held by the TPM. Use
+    /// `CertifiedKey::verify` to check the result; its `data` and
+    /// `signature` are the validation data the TPM returned.
+    pub fn certify_key(&self, certifier: &TssRsaKey, anti_replay: &[u8; 20]) -> Result<CertifiedKey, TssError> {
+        let mut validation_data = TSS_VALIDATION { versionInfo: TSS_VERSION::default(), ulExternalDataLength: 20, rgbExternalData: anti_replay.as_ptr() as *mut u8, ulDataLength: 0, rgbData: ptr::null_mut(), ulValidationDataLength: 0, rgbValidationData: ptr::null_mut() };
+        let result = unsafe {
+            Tspi_Key_CertifyKey(self.handle, certifier.handle, &mut validation_data)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let data = copy_raw_ptr_to_vec(validation_data.rgbData, validation_data.ulDataLength as usize);
+        let signature = copy_raw_ptr_to_vec(validation_data.rgbValidationData, validation_data.ulValidationDataLength as usize);
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbData);
+            Tspi_Context_FreeMemory(self.context.handle, validation_data.rgbValidationData);
+        }
+        CertifiedKey::new(&data, &signature).map_err(|_| internal_error())
+    }
+
+    /// Loads an encoded `TPM_PUBKEY` into this key object, for keys that
+    /// exist only outside the TPM, such as a privacy CA's.
+    pub fn set_pub_key(&self, tpm_pubkey: &[u8]) -> Result<(), TssError> {
+        self.set_attrib_data(TSS_TSPATTRIB_KEY_BLOB, TSS_TSPATTRIB_KEYBLOB_PUBLIC_KEY, tpm_pubkey)
+    }
+
+    /// Returns the key's wrapped `TPM_KEY` or `TPM_KEY12` blob.
+    pub fn get_key_blob(&self) -> Result<Vec<u8>, TssError> {
+        get_attrib_data_impl(self, self.context, TSS_TSPATTRIB_KEY_BLOB, TSS_TSPATTRIB_KEYBLOB_BLOB)
+    }
+
+    /// Returns the key's usage.
+    pub fn get_key_type(&self) -> Result<TssKeyType, TssError> {
+        match get_attrib_uint32_impl(self, TSS_TSPATTRIB_KEY_INFO, TSS_TSPATTRIB_KEYINFO_USAGE)? {
+            TSS_KEYUSAGE_BIND => Ok(TssKeyType::Bind),
+            TSS_KEYUSAGE_IDENTITY => Ok(TssKeyType::Identity),
+            TSS_KEYUSAGE_LEGACY => Ok(TssKeyType::Legacy),
+            TSS_KEYUSAGE_SIGN => Ok(TssKeyType::Signing),
+            TSS_KEYUSAGE_STORAGE => Ok(TssKeyType::Storage),
+            TSS_KEYUSAGE_AUTHCHANGE => Ok(TssKeyType::AuthChange),
+            TSS_KEYUSAGE_MIGRATE => Ok(TssKeyType::Migrate),
+            _ => Err(internal_error())
+        }
+    }
+
+    /// Loads the key into the TPM under `unwrapping_key`, its parent.
+    pub fn load_key(&self, unwrapping_key: &TssRsaKey) -> Result<(), TssError> {
+        let result = unsafe {
+            Tspi_Key_LoadKey(self.handle, unwrapping_key.handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(())
+    }
+}
+
+impl<'c> TssObject for TssTPM<'c> {
+    fn get_handle(&self) -> TssHObject { self.handle }
+    fn set_attrib_uint32(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib: u32) -> Result<(), TssError> {
+        set_attrib_uint32_impl(self, attrib_flag, sub_flag, attrib)
+    }
+    fn set_attrib_data(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib_data: &[u8]) -> Result<(), TssError> {
+        set_attrib_data_impl(self, attrib_flag, sub_flag, attrib_data)
+    }
+}
+
+impl<'c> TssChangeAuth for TssTPM<'c> {}
+
+impl<'c> TssNvStore<'c> {
+    /// Sets the size in bytes of the area `define_space` creates.
+    pub fn set_size(&self, size: u32) -> Result<(), TssError> {
+        self.set_attrib_uint32(TSS_TSPATTRIB_NV_DATASIZE, 0, size)
+    }
+
+    /// Sets the permissions of the area `define_space` creates.
+    pub fn set_permissions(&self, permissions: NvPermissions) -> Result<(), TssError> {
+        self.set_attrib_uint32(TSS_TSPATTRIB_NV_PERMISSIONS, 0, permissions.bits())
+    }
+
+    /// Creates the NV area. Reads and writes are further gated on the PCRs
+    /// selected for release in `read_pcrs` and `write_pcrs`, if given.
+    ///
+    /// Needs owner authorization through the TPM object's policy. With
+    /// `AUTH_READ` or `AUTH_WRITE` the area's secret comes from this
+    /// object's usage policy.
+    pub fn define_space(&self, read_pcrs: Option<&TssPCRCompositeInfoShort>, write_pcrs: Option<&TssPCRCompositeInfoShort>) -> Result<(), TssError> {
+        let read_pcrs_handle = read_pcrs.map_or(0, |pcrs| pcrs.handle);
+        let write_pcrs_handle = write_pcrs.map_or(0, |pcrs| pcrs.handle);
+        let result = unsafe {
+            Tspi_NV_DefineSpace(self.handle, read_pcrs_handle, write_pcrs_handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(())
+    }
+
+    /// Deletes the NV area. Needs owner authorization.
+    pub fn release_space(&self) -> Result<(), TssError> {
+        let result = unsafe {
+            Tspi_NV_ReleaseSpace(self.handle)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(())
+    }
+
+    /// Writes `data` at `offset` in a single TPM command. A zero length
+    /// write sets the write lock of `WRITE_DEFINE` and `WRITE_STCLEAR`
+    /// areas.
+    pub fn write_value(&self, offset: u32, data: &[u8]) -> Result<(), TssError> {
+        let result = unsafe {
+            Tspi_NV_WriteValue(self.handle, offset, data.len() as u32, data.as_ptr() as *mut u8)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        Ok(())
+    }
+
+    /// Writes `data` at `offset`, split into commands that fit the TPM's
+    /// input buffer. Not atomic: a failure can leave earlier pieces
+    /// written. `WRITE_ALL` areas must fit in one command.
+    pub fn write(&self, offset: u32, data: &[u8]) -> Result<(), TssError> {
+        let chunk_size = self.max_chunk_size()?;
+        let mut chunk_offset = offset;
+        for chunk in data.chunks(chunk_size) {
+            self.write_value(chunk_offset, chunk)?;
+            chunk_offset += chunk.len() as u32;
+        }
+        Ok(())
+    }
+
+    /// Reads `length` bytes at `offset`, split into commands that fit the
+    /// TPM's buffers. The buffer size is queried once per store.
+    pub fn read(&self, offset: u32, length: usize) -> Result<Vec<u8>, TssError> {
+        let chunk_size = self.max_chunk_size()?;
+        let mut data = Vec::with_capacity(length);
+        while data.len() < length {
+            let chunk_length = cmp::min(length - data.len(), chunk_size);
+            let chunk = self.read_value(offset + data.len() as u32, chunk_length as u32)?;
+            if chunk.is_empty() {
+                return Err(internal_error());
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data)
+    }
+
+    fn max_chunk_size(&self) -> Result<usize, TssError> {
+        if let Some(chunk_size) = self.chunk_size.get() {
+            return Ok(chunk_size);
+        }
+        let input_buffer_size = self.context.get_tpm_object()?.get_property(TSS_TPMCAP_PROP_INPUTBUFFERSIZE)?;
+        if input_buffer_size <= NV_COMMAND_OVERHEAD {
+            return Err(internal_error());
+        }
+        let chunk_size = (input_buffer_size - NV_COMMAND_OVERHEAD) as usize;
+        self.chunk_size.set(Some(chunk_size));
+        Ok(chunk_size)
+    }
+
+    /// Reads up to `length` bytes at `offset` in a single TPM command.
+    pub fn read_value(&self, offset: u32, length: u32) -> Result<Vec<u8>, TssError> {
+        let mut data_length = length;
+        let mut data_ptr = ptr::null_mut();
+        let result = unsafe {
+            Tspi_NV_ReadValue(self.handle, offset, &mut data_length, &mut data_ptr)
+        };
+        if result != TSS_SUCCESS {
+            return Err(TssError { result });
+        }
+        let data = copy_raw_ptr_to_vec(data_ptr, data_length as usize);
+        unsafe {
+            Tspi_Context_FreeMemory(self.context.handle, data_ptr);
+        }
+        Ok(data)
+    }
+}
+
+impl<'c> TssObject for TssNvStore<'c> {
+    fn get_handle(&self) -> TssHObject { self.handle }
+    fn set_attrib_uint32(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib: u32) -> Result<(), TssError> {
+        set_attrib_uint32_impl(self, attrib_flag, sub_flag, attrib)
     }
     fn set_attrib_data(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib_data: &[u8]) -> Result<(), TssError> {
         set_attrib_data_impl(self, attrib_flag, sub_flag, attrib_data)
     }
 }
 
+impl<'c> TssObject for TssRsaKey<'c> {
+    fn get_handle(&self) -> TssHObject { self.handle }
+    fn set_attrib_uint32(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib: u32) -> Result<(), TssError> {
+        set_attrib_uint32_impl(self, attrib_flag, sub_flag, attrib)
+    }
+    fn set_attrib_data(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib_data: &[u8]) -> Result<(), TssError> {
+        set_attrib_data_impl(self, attrib_flag, sub_flag, attrib_data)
+    }
+}
+
+impl<'c> TssChangeAuth for TssRsaKey<'c> {}
+
+impl<'c> TssChangeAuthAsym for TssRsaKey<'c> {}
+
+impl<'c> TssEncData<'c> {
+    /// Loads a `TPM_STORED_DATA` blob, such as sealed data, into this
+    /// object.
+    pub fn set_blob(&self, blob: &[u8]) -> Result<(), TssError> {
+        self.set_attrib_data(TSS_TSPATTRIB_ENCDATA_BLOB, TSS_TSPATTRIB_ENCDATABLOB_BLOB, blob)
+    }
+
+    pub fn get_blob(&self) -> Result<Vec<u8>, TssError> {
+        get_attrib_data_impl(self, self.context, TSS_TSPATTRIB_ENCDATA_BLOB, TSS_TSPATTRIB_ENCDATABLOB_BLOB)
+    }
+}
+
+impl<'c> TssObject for TssEncData<'c> {
+    fn get_handle(&self) -> TssHObject { self.handle }
+    fn set_attrib_uint32(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib: u32) -> Result<(), TssError> {
+        set_attrib_uint32_impl(self, attrib_flag, sub_flag, attrib)
+    }
+    fn set_attrib_data(&self, attrib_flag: TssFlag, sub_flag: TssFlag, attrib_data: &[u8]) -> Result<(), TssError> {
+        set_attrib_data_impl(self, attrib_flag, sub_flag, attrib_data)
+    }
+}
+
+impl<'c> TssChangeAuth for TssEncData<'c> {}
+
+impl<'c> TssChangeAuthAsym for TssEncData<'c> {}
+
 fn pcr_composite_select_pcr_index_ex(handle: TssHPCRS, pcr_index: u32, direction: u32) -> Result<(), TssError> {
     let result = unsafe {
         Tspi_PcrComposite_SelectPcrIndexEx(handle, pcr_index, direction)
@@ -472,7 +1826,7 @@ fn pcr_composite_select_pcr_index_ex(handle: TssHPCRS, pcr_index: u32, direction
 }
 
 impl<'c> TssPCRCompositeInfo<'c> {
-    fn select_pcr_index(&self, pcr_index: u32) -> Result<(), TssError> {
+    pub fn select_pcr_index(&self, pcr_index: u32) -> Result<(), TssError> {
         let result = unsafe {
             Tspi_PcrComposite_SelectPcrIndex(self.handle, pcr_index)
         };
@@ -0,0 +1,10 @@
+use std::ptr;
+use std::sync::atomic;
+
+/// Overwrites `buffer` with zeros in a way the optimizer won't remove.
+pub(crate) fn wipe(buffer: &mut [u8]) {
+    for byte in buffer.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    atomic::compiler_fence(atomic::Ordering::SeqCst);
+}
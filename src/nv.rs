@@ -0,0 +1,253 @@
+use std::fmt;
+use std::ops;
+
+use trousers_sys::tspi::TPM_NV_ATTRIBUTES;
+
+use blob::{BlobReader, ParseError};
+use ekcert::TPM_NV_INDEX_EKCERT;
+use quote::PcrInfoShort;
+
+pub const TPM_NV_INDEX_LOCK: u32 = 0xffffffff;
+pub const TPM_NV_INDEX0: u32 = 0x00000000;
+pub const TPM_NV_INDEX_DIR: u32 = 0x10000001;
+
+pub const TPM_TAG_NV_ATTRIBUTES: u16 = 0x0017;
+pub const TPM_TAG_NV_DATA_PUBLIC: u16 = 0x0018;
+
+const TPM_NV_INDEX_T_BIT: u32 = 0x80000000;
+const TPM_NV_INDEX_P_BIT: u32 = 0x40000000;
+const TPM_NV_INDEX_U_BIT: u32 = 0x20000000;
+const TPM_NV_INDEX_D_BIT: u32 = 0x10000000;
+
+/// A `TPM_NV_INDEX`, naming an NV area.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NvIndex(pub u32);
+
+impl NvIndex {
+    /// Defining this index with size 0 sets the TPM's `nvLocked` flag, after
+    /// which NV permissions are enforced even without an owner.
+    pub const LOCK: NvIndex = NvIndex(TPM_NV_INDEX_LOCK);
+    pub const INDEX0: NvIndex = NvIndex(TPM_NV_INDEX0);
+    /// The TPM 1.1 data integrity register.
+    pub const DIR: NvIndex = NvIndex(TPM_NV_INDEX_DIR);
+    pub const EK_CERT: NvIndex = NvIndex(TPM_NV_INDEX_EKCERT);
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+
+    /// Whether the index is reserved to the TPM manufacturer.
+    pub fn is_tpm_reserved(&self) -> bool {
+        self.0 & TPM_NV_INDEX_T_BIT != 0
+    }
+
+    /// Whether the index is reserved to the platform manufacturer.
+    pub fn is_platform(&self) -> bool {
+        self.0 & TPM_NV_INDEX_P_BIT != 0
+    }
+
+    /// Whether the index is reserved to the platform user.
+    pub fn is_platform_user(&self) -> bool {
+        self.0 & TPM_NV_INDEX_U_BIT != 0
+    }
+
+    /// Whether the area is permanent: defined by the manufacturer and never
+    /// releasable.
+    pub fn is_permanent(&self) -> bool {
+        self.0 & TPM_NV_INDEX_D_BIT != 0
+    }
+}
+
+impl From<u32> for NvIndex {
+    fn from(index: u32) -> NvIndex {
+        NvIndex(index)
+    }
+}
+
+impl fmt::Display for NvIndex {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:#010x}", self.0)
+    }
+}
+
+/// `TPM_NV_PER_*` permission bits of an NV area, combined with `|`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NvPermissions(pub u32);
+
+impl NvPermissions {
+    pub const NONE: NvPermissions = NvPermissions(0);
+    /// Writing needs physical presence.
+    pub const PP_WRITE: NvPermissions = NvPermissions(1 << 0);
+    /// Writing needs owner authorization.
+    pub const OWNER_WRITE: NvPermissions = NvPermissions(1 << 1);
+    /// Writing needs the area's own authorization.
+    pub const AUTH_WRITE: NvPermissions = NvPermissions(1 << 2);
+    /// Writes must cover the whole area at once.
+    pub const WRITE_ALL: NvPermissions = NvPermissions(1 << 12);
+    /// Write once: a zero length write locks the area permanently.
+    pub const WRITE_DEFINE: NvPermissions = NvPermissions(1 << 13);
+    /// A zero length write locks the area until the next reboot.
+    pub const WRITE_STCLEAR: NvPermissions = NvPermissions(1 << 14);
+    /// Writing index 0 with size 0 locks the area until the next reboot.
+    pub const GLOBAL_LOCK: NvPermissions = NvPermissions(1 << 15);
+    /// Reading needs physical presence.
+    pub const PP_READ: NvPermissions = NvPermissions(1 << 16);
+    /// Reading needs owner authorization.
+    pub const OWNER_READ: NvPermissions = NvPermissions(1 << 17);
+    /// Reading needs the area's own authorization.
+    pub const AUTH_READ: NvPermissions = NvPermissions(1 << 18);
+    /// A zero length read locks reading until the next reboot.
+    pub const READ_STCLEAR: NvPermissions = NvPermissions(1 << 31);
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn contains(&self, other: NvPermissions) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl ops::BitOr for NvPermissions {
+    type Output = NvPermissions;
+
+    fn bitor(self, other: NvPermissions) -> NvPermissions {
+        NvPermissions(self.0 | other.0)
+    }
+}
+
+impl ops::BitOrAssign for NvPermissions {
+    fn bitor_assign(&mut self, other: NvPermissions) {
+        self.0 |= other.0;
+    }
+}
+
+impl From<TPM_NV_ATTRIBUTES> for NvPermissions {
+    fn from(attributes: TPM_NV_ATTRIBUTES) -> NvPermissions {
+        NvPermissions(attributes.attributes)
+    }
+}
+
+/// Decodes the response to a `TSS_TPMCAP_NV_LIST` query.
+pub fn parse_nv_list(data: &[u8]) -> Result<Vec<NvIndex>, ParseError> {
+    let mut reader = BlobReader::new(data);
+    let mut indices = Vec::new();
+    while !reader.is_empty() {
+        indices.push(NvIndex(reader.read_u32()?));
+    }
+    Ok(indices)
+}
+
+/// A `TPM_NV_DATA_PUBLIC`, describing a defined NV area.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NvDataPublic {
+    pub index: NvIndex,
+    /// PCRs and localities reads are restricted to.
+    pub pcr_info_read: PcrInfoShort,
+    /// PCRs and localities writes are restricted to.
+    pub pcr_info_write: PcrInfoShort,
+    pub permissions: NvPermissions,
+    /// Reading is locked until the next reboot.
+    pub read_stclear: bool,
+    /// Writing is locked until the next reboot.
+    pub write_stclear: bool,
+    /// Writing is locked for good.
+    pub write_define: bool,
+    pub data_size: u32
+}
+
+impl NvDataPublic {
+    pub fn parse(blob: &[u8]) -> Result<NvDataPublic, ParseError> {
+        let mut reader = BlobReader::new(blob);
+        if reader.read_u16()? != TPM_TAG_NV_DATA_PUBLIC {
+            return Err(reader.invalid("TPM_NV_DATA_PUBLIC.tag"));
+        }
+        let index = NvIndex(reader.read_u32()?);
+        let pcr_info_read = PcrInfoShort::read(&mut reader)?;
+        let pcr_info_write = PcrInfoShort::read(&mut reader)?;
+        if reader.read_u16()? != TPM_TAG_NV_ATTRIBUTES {
+            return Err(reader.invalid("TPM_NV_ATTRIBUTES.tag"));
+        }
+        let permissions = NvPermissions(reader.read_u32()?);
+        let read_stclear = reader.read_u8()? != 0;
+        let write_stclear = reader.read_u8()? != 0;
+        let write_define = reader.read_u8()? != 0;
+        let data_size = reader.read_u32()?;
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_NV_DATA_PUBLIC"));
+        }
+        Ok(NvDataPublic { index, pcr_info_read, pcr_info_write, permissions, read_stclear, write_stclear, write_define, data_size })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blob::ParseError;
+    use quote::PcrSelection;
+    use test_util::hex;
+    use super::*;
+
+    // EK certificate area readable at PCRs 0 and 10 from any locality,
+    // writable by the owner from locality 0 only
+    fn nv_data_public() -> Vec<u8> {
+        let mut data = hex("00181000f000");
+        data.extend_from_slice(&hex("00030104001f"));
+        data.extend_from_slice(&[0xaa; 20]);
+        data.extend_from_slice(&hex("000300000001"));
+        data.extend_from_slice(&[0; 20]);
+        data.extend_from_slice(&hex("001700040002000100"));
+        data.extend_from_slice(&hex("00000500"));
+        data
+    }
+
+    #[test]
+    fn parses_nv_data_public() {
+        let nv_data_public = NvDataPublic::parse(&nv_data_public()).unwrap();
+        assert_eq!(nv_data_public.index, NvIndex::EK_CERT);
+        assert_eq!(nv_data_public.pcr_info_read, PcrInfoShort {
+            pcr_selection: PcrSelection { pcr_select: vec![0x01, 0x04, 0x00] },
+            locality_at_release: 0x1f,
+            digest_at_release: [0xaa; 20]
+        });
+        assert_eq!(nv_data_public.pcr_info_read.pcr_selection.pcr_indices(), vec![0, 10]);
+        assert_eq!(nv_data_public.pcr_info_write.pcr_selection.pcr_indices(), Vec::<u32>::new());
+        assert_eq!(nv_data_public.pcr_info_write.locality_at_release, 0x01);
+        assert_eq!(nv_data_public.permissions, NvPermissions::OWNER_WRITE | NvPermissions::AUTH_READ);
+        assert!(!nv_data_public.read_stclear);
+        assert!(nv_data_public.write_stclear);
+        assert!(!nv_data_public.write_define);
+        assert_eq!(nv_data_public.data_size, 0x500);
+    }
+
+    #[test]
+    fn rejects_truncated_nv_data_public() {
+        let data = nv_data_public();
+        assert_eq!(NvDataPublic::parse(&data[..data.len() - 1]), Err(ParseError::UnexpectedEnd { offset: 67 }));
+        assert_eq!(NvDataPublic::parse(&data[..20]), Err(ParseError::UnexpectedEnd { offset: 12 }));
+    }
+
+    #[test]
+    fn rejects_bad_attributes_tag() {
+        let mut data = nv_data_public();
+        data[59] = 0x18;
+        assert_eq!(NvDataPublic::parse(&data), Err(ParseError::InvalidValue { offset: 60, field: "TPM_NV_ATTRIBUTES.tag" }));
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let mut data = nv_data_public();
+        data.push(0);
+        assert_eq!(NvDataPublic::parse(&data), Err(ParseError::InvalidValue { offset: 71, field: "TPM_NV_DATA_PUBLIC" }));
+    }
+
+    #[test]
+    fn parses_nv_list() {
+        assert_eq!(parse_nv_list(&hex("1000f00000000001")), Ok(vec![NvIndex::EK_CERT, NvIndex(1)]));
+        assert_eq!(parse_nv_list(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_partial_nv_list_entry() {
+        assert_eq!(parse_nv_list(&hex("1000f0000000")), Err(ParseError::UnexpectedEnd { offset: 4 }));
+    }
+}
@@ -0,0 +1,428 @@
+use std::error;
+use std::fmt;
+
+use aes::{Aes128, Aes192, Aes256};
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockCipher, BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit};
+use des::TdesEde3;
+use rsa::rand_core::CryptoRngCore;
+use rsa::{Oaep, Pkcs1v15Encrypt};
+use sha1::{Digest, Sha1};
+
+use blob::{BlobReader, ParseError};
+use ekcert;
+use identity::{IdentityProof, IdentityRequest, KeyParms, SymmetricKeyParms, TPM_ALG_3DES, TPM_ALG_AES128, TPM_ALG_AES192, TPM_ALG_AES256};
+use pubkey::{RsaPublicKey, TPM_ALG_RSA, TPM_ES_RSAESOAEP_SHA1_MGF1, TPM_ES_RSAESPKCSV15, TPM_SS_NONE};
+use verify::VerifyError;
+
+pub const TPM_ORD_MAKE_IDENTITY: u32 = 0x00000079;
+
+pub const TPM_ES_SYM_CTR: u16 = 0x0004;
+pub const TPM_ES_SYM_OFB: u16 = 0x0005;
+pub const TPM_ES_SYM_CBC_PKCS5PAD: u16 = 0x00ff;
+
+pub const TPM_TAG_EK_BLOB: u16 = 0x000c;
+pub const TPM_TAG_EK_BLOB_ACTIVATE: u16 = 0x002b;
+pub const TPM_EK_TYPE_ACTIVATE: u16 = 0x0001;
+
+// OAEP encoding parameter the TPM uses for everything it decrypts.
+const OAEP_LABEL: &str = "TCPA";
+
+/// Error returned while answering an identity request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrivacyCaError {
+    Parse(ParseError),
+    Verify(VerifyError),
+    /// The request uses a key algorithm the CA doesn't implement.
+    UnsupportedAlgorithm(u32),
+    /// The request uses an encryption scheme the CA doesn't implement.
+    UnsupportedScheme(u16),
+    /// The identity proof carries no endorsement credential, so nothing
+    /// ties the identity key to a genuine TPM.
+    MissingEndorsementCredential,
+    /// The request couldn't be decrypted with the CA's key.
+    Decrypt,
+    /// The endorsement key can't be encrypted to.
+    Encrypt
+}
+
+impl fmt::Display for PrivacyCaError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PrivacyCaError::Parse(ref error) => write!(fmt, "malformed identity request: {}", error),
+            PrivacyCaError::Verify(ref error) => write!(fmt, "identity proof rejected: {}", error),
+            PrivacyCaError::UnsupportedAlgorithm(algorithm_id) => write!(fmt, "unsupported algorithm {:#x}", algorithm_id),
+            PrivacyCaError::UnsupportedScheme(scheme) => write!(fmt, "unsupported encryption scheme {:#x}", scheme),
+            PrivacyCaError::MissingEndorsementCredential => fmt.write_str("identity proof has no endorsement credential"),
+            PrivacyCaError::Decrypt => fmt.write_str("identity request decryption failed"),
+            PrivacyCaError::Encrypt => fmt.write_str("encryption to the endorsement key failed")
+        }
+    }
+}
+
+impl error::Error for PrivacyCaError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            PrivacyCaError::Parse(ref error) => Some(error),
+            PrivacyCaError::Verify(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<ParseError> for PrivacyCaError {
+    fn from(error: ParseError) -> PrivacyCaError {
+        PrivacyCaError::Parse(error)
+    }
+}
+
+impl From<VerifyError> for PrivacyCaError {
+    fn from(error: VerifyError) -> PrivacyCaError {
+        PrivacyCaError::Verify(error)
+    }
+}
+
+/// A `TPM_SYMMETRIC_KEY`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymmetricKey {
+    pub algorithm_id: u32,
+    pub enc_scheme: u16,
+    pub data: Vec<u8>
+}
+
+impl SymmetricKey {
+    pub fn parse(blob: &[u8]) -> Result<SymmetricKey, ParseError> {
+        let mut reader = BlobReader::new(blob);
+        let algorithm_id = reader.read_u32()?;
+        let enc_scheme = reader.read_u16()?;
+        let data = reader.read_sized_u16()?.to_vec();
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_SYMMETRIC_KEY"));
+        }
+        Ok(SymmetricKey { algorithm_id, enc_scheme, data })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.algorithm_id.to_be_bytes());
+        out.extend_from_slice(&self.enc_scheme.to_be_bytes());
+        out.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+}
+
+/// Which structure `build_activation` encrypts to the endorsement key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivationFormat {
+    /// A `TPM_ASYM_CA_CONTENTS`, understood by TPM 1.1 and 1.2.
+    AsymCaContents,
+    /// A `TPM_EK_BLOB` holding a `TPM_EK_BLOB_ACTIVATE` with no PCR
+    /// binding. TPM 1.2 only.
+    EkBlob
+}
+
+/// The privacy CA's answer to an identity request, passed as is to
+/// `TssTPM::activate_identity`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Activation {
+    pub asym_ca_contents: Vec<u8>,
+    pub sym_ca_attestation: Vec<u8>
+}
+
+/// A software privacy CA, certifying identity keys made with
+/// `TssTPM::collate_identity_request`.
+///
+/// Checking the endorsement credential's issuer chain and deciding which
+/// platforms to trust are left to the caller.
+pub struct PrivacyCa {
+    private_key: rsa::RsaPrivateKey,
+    public_key: RsaPublicKey
+}
+
+impl PrivacyCa {
+    pub fn new(private_key: rsa::RsaPrivateKey) -> PrivacyCa {
+        let public_key = RsaPublicKey::from_rsa(&private_key.to_public_key(), TPM_ES_RSAESOAEP_SHA1_MGF1, TPM_SS_NONE);
+        PrivacyCa { private_key, public_key }
+    }
+
+    /// The CA's key as clients load it with `TssRsaKey::set_pub_key`, after
+    /// `RsaPublicKey::to_tpm_pubkey`.
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.public_key
+    }
+
+    /// Recovers the session key from `asym_blob` and decrypts the
+    /// `TPM_IDENTITY_PROOF` in `sym_blob` with it.
+    ///
+    /// The IV is taken from the request's symmetric parameters, or from the
+    /// first block of `sym_blob` when the parameters have none or the blob
+    /// repeats it, as TrouSerS does.
+    pub fn decrypt_request(&self, request: &IdentityRequest) -> Result<IdentityProof, PrivacyCaError> {
+        if request.asym_algorithm.algorithm_id != TPM_ALG_RSA {
+            return Err(PrivacyCaError::UnsupportedAlgorithm(request.asym_algorithm.algorithm_id));
+        }
+        let session_key = match request.asym_algorithm.enc_scheme {
+            TPM_ES_RSAESOAEP_SHA1_MGF1 => self.private_key.decrypt(Oaep::new_with_label::<Sha1, _>(OAEP_LABEL), &request.asym_blob),
+            TPM_ES_RSAESPKCSV15 => self.private_key.decrypt(Pkcs1v15Encrypt, &request.asym_blob),
+            scheme => return Err(PrivacyCaError::UnsupportedScheme(scheme))
+        }.map_err(|_| PrivacyCaError::Decrypt)?;
+        let session_key = SymmetricKey::parse(&session_key)?;
+        if session_key.enc_scheme == TPM_ES_SYM_CTR || session_key.enc_scheme == TPM_ES_SYM_OFB {
+            return Err(PrivacyCaError::UnsupportedScheme(session_key.enc_scheme));
+        }
+        let block_size = block_size(session_key.algorithm_id)?;
+        let iv = request.sym_algorithm.symmetric_parms()?.iv;
+        let (iv, ciphertext) = if iv.is_empty() || request.sym_blob.starts_with(&iv) {
+            if request.sym_blob.len() < block_size {
+                return Err(PrivacyCaError::Decrypt);
+            }
+            request.sym_blob.split_at(block_size)
+        } else {
+            (&iv[..], &request.sym_blob[..])
+        };
+        let proof = cbc_decrypt(&session_key, iv, ciphertext)?;
+        Ok(IdentityProof::parse(&proof)?)
+    }
+
+    /// Checks that the identity binding in `proof` is the identity key's
+    /// signature over the `TPM_IDENTITY_CONTENTS` naming this CA, and that
+    /// the endorsement credential certifies `ek`.
+    pub fn verify_proof(&self, proof: &IdentityProof, ek: &RsaPublicKey) -> Result<(), PrivacyCaError> {
+        let contents = identity_contents(&proof.label, &self.public_key, &proof.identity_key);
+        proof.identity_key.verify_sha1(&contents, &proof.identity_binding)?;
+        if proof.endorsement_credential.is_empty() {
+            return Err(PrivacyCaError::MissingEndorsementCredential);
+        }
+        ekcert::verify_ek_certificate_key(&proof.endorsement_credential, ek)?;
+        Ok(())
+    }
+}
+
+/// Encodes the `TPM_IDENTITY_CONTENTS` a TPM signs in `TPM_MakeIdentity`.
+pub fn identity_contents(label: &[u8], ca_public_key: &RsaPublicKey, identity_key: &RsaPublicKey) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(label);
+    hasher.update(ca_public_key.to_tpm_pubkey());
+    let mut out = vec![1, 1, 0, 0];
+    out.extend_from_slice(&TPM_ORD_MAKE_IDENTITY.to_be_bytes());
+    out.extend_from_slice(&hasher.finalize());
+    out.extend_from_slice(&identity_key.to_tpm_pubkey());
+    out
+}
+
+/// Encrypts `credential` for the TPM holding `ek` so that only
+/// `TssTPM::activate_identity` with `identity_key` recovers it. The
+/// credential is encrypted with a fresh AES-128 session key, prefixed by
+/// its IV as TrouSerS expects.
+pub fn build_activation<R: CryptoRngCore>(identity_key: &RsaPublicKey, ek: &RsaPublicKey, credential: &[u8], format: ActivationFormat, rng: &mut R) -> Result<Activation, PrivacyCaError> {
+    let mut key_data = vec![0; 16];
+    rng.fill_bytes(&mut key_data);
+    let session_key = SymmetricKey { algorithm_id: TPM_ALG_AES128, enc_scheme: TPM_ES_SYM_CBC_PKCS5PAD, data: key_data };
+    let mut iv = vec![0; 16];
+    rng.fill_bytes(&mut iv);
+
+    let mut encrypted_credential = iv.clone();
+    encrypted_credential.extend_from_slice(&aes128_cbc_encrypt(&session_key.data, &iv, credential)?);
+    let parms = SymmetricKeyParms { key_length: 128, block_size: 128, iv };
+    let algorithm = KeyParms { algorithm_id: TPM_ALG_AES128, enc_scheme: TPM_ES_SYM_CBC_PKCS5PAD, sig_scheme: TPM_SS_NONE, parms: parms.encode() };
+    let mut sym_ca_attestation = Vec::new();
+    sym_ca_attestation.extend_from_slice(&(encrypted_credential.len() as u32).to_be_bytes());
+    algorithm.write(&mut sym_ca_attestation);
+    sym_ca_attestation.extend_from_slice(&encrypted_credential);
+
+    let id_digest = Sha1::digest(identity_key.to_tpm_pubkey());
+    let asym_contents = match format {
+        ActivationFormat::AsymCaContents => {
+            let mut out = session_key.encode();
+            out.extend_from_slice(&id_digest);
+            out
+        },
+        ActivationFormat::EkBlob => {
+            let mut activate = Vec::new();
+            activate.extend_from_slice(&TPM_TAG_EK_BLOB_ACTIVATE.to_be_bytes());
+            activate.extend_from_slice(&session_key.encode());
+            activate.extend_from_slice(&id_digest);
+            // TPM_PCR_INFO_SHORT selecting no PCRs, releasable at any locality.
+            activate.extend_from_slice(&[0, 3, 0, 0, 0, 0x1f]);
+            activate.extend_from_slice(&[0; 20]);
+            let mut out = Vec::new();
+            out.extend_from_slice(&TPM_TAG_EK_BLOB.to_be_bytes());
+            out.extend_from_slice(&TPM_EK_TYPE_ACTIVATE.to_be_bytes());
+            out.extend_from_slice(&(activate.len() as u32).to_be_bytes());
+            out.extend_from_slice(&activate);
+            out
+        }
+    };
+    let asym_ca_contents = ek.to_rsa()?
+        .encrypt(rng, Oaep::new_with_label::<Sha1, _>(OAEP_LABEL), &asym_contents)
+        .map_err(|_| PrivacyCaError::Encrypt)?;
+    Ok(Activation { asym_ca_contents, sym_ca_attestation })
+}
+
+fn block_size(algorithm_id: u32) -> Result<usize, PrivacyCaError> {
+    match algorithm_id {
+        TPM_ALG_AES128 | TPM_ALG_AES192 | TPM_ALG_AES256 => Ok(16),
+        TPM_ALG_3DES => Ok(8),
+        _ => Err(PrivacyCaError::UnsupportedAlgorithm(algorithm_id))
+    }
+}
+
+fn cbc_decrypt(key: &SymmetricKey, iv: &[u8], data: &[u8]) -> Result<Vec<u8>, PrivacyCaError> {
+    fn decrypt<C: BlockCipher + BlockDecryptMut + KeyInit>(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, PrivacyCaError> {
+        cbc::Decryptor::<C>::new_from_slices(key, iv)
+            .map_err(|_| PrivacyCaError::Decrypt)?
+            .decrypt_padded_vec_mut::<Pkcs7>(data)
+            .map_err(|_| PrivacyCaError::Decrypt)
+    }
+    match key.algorithm_id {
+        TPM_ALG_AES128 => decrypt::<Aes128>(&key.data, iv, data),
+        TPM_ALG_AES192 => decrypt::<Aes192>(&key.data, iv, data),
+        TPM_ALG_AES256 => decrypt::<Aes256>(&key.data, iv, data),
+        TPM_ALG_3DES => decrypt::<TdesEde3>(&key.data, iv, data),
+        algorithm_id => Err(PrivacyCaError::UnsupportedAlgorithm(algorithm_id))
+    }
+}
+
+fn aes128_cbc_encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, PrivacyCaError> {
+    let encryptor = cbc::Encryptor::<Aes128>::new_from_slices(key, iv).map_err(|_| PrivacyCaError::Encrypt)?;
+    Ok(encryptor.encrypt_padded_vec_mut::<Pkcs7>(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+    use rsa::RsaPrivateKey;
+
+    use identity::SymmetricKeyParms;
+    use test_util::{key_a, key_b, key_c, sign_sha1, signing_public_key};
+    use super::*;
+
+    // A self-signed certificate for key C, the endorsement key
+    const EK_CERT: &[u8] = include_bytes!("../testdata/test_ek_cert.der");
+    const LABEL: &[u8] = b"test identity";
+
+    fn ek() -> RsaPublicKey {
+        RsaPublicKey::from_rsa(&key_c().to_public_key(), TPM_ES_RSAESOAEP_SHA1_MGF1, TPM_SS_NONE)
+    }
+
+    // Builds a TPM_IDENTITY_PROOF for identity key B the way
+    // TPM_MakeIdentity would
+    fn identity_proof(ca: &PrivacyCa, endorsement_credential: &[u8]) -> Vec<u8> {
+        let identity_key = signing_public_key(&key_b()).to_tpm_pubkey();
+        let binding = sign_sha1(&key_b(), &identity_contents(LABEL, ca.public_key(), &signing_public_key(&key_b())));
+        let mut proof = vec![1, 1, 0, 0];
+        for size in &[LABEL.len(), binding.len(), endorsement_credential.len(), 0, 0] {
+            proof.extend_from_slice(&(*size as u32).to_be_bytes());
+        }
+        proof.extend_from_slice(&identity_key);
+        proof.extend_from_slice(LABEL);
+        proof.extend_from_slice(&binding);
+        proof.extend_from_slice(endorsement_credential);
+        proof
+    }
+
+    // Encrypts `proof` into a TPM_IDENTITY_REQ the way
+    // Tspi_TPM_CollateIdentityRequest does, with the IV prepended to the
+    // symmetric blob
+    fn identity_request(ca: &PrivacyCa, proof: &[u8]) -> Vec<u8> {
+        let session_key = SymmetricKey { algorithm_id: TPM_ALG_AES128, enc_scheme: TPM_ES_SYM_CBC_PKCS5PAD, data: vec![0x5e; 16] };
+        let iv = vec![0x1f; 16];
+        let mut sym_blob = iv.clone();
+        sym_blob.extend_from_slice(&aes128_cbc_encrypt(&session_key.data, &iv, proof).unwrap());
+        let asym_blob = ca.public_key().to_rsa().unwrap()
+            .encrypt(&mut thread_rng(), Oaep::new_with_label::<Sha1, _>(OAEP_LABEL), &session_key.encode())
+            .unwrap();
+        let asym_algorithm = KeyParms { algorithm_id: TPM_ALG_RSA, enc_scheme: TPM_ES_RSAESOAEP_SHA1_MGF1, sig_scheme: TPM_SS_NONE, parms: vec![0, 0, 4, 0, 0, 0, 0, 2, 0, 0, 0, 0] };
+        let parms = SymmetricKeyParms { key_length: 128, block_size: 128, iv };
+        let sym_algorithm = KeyParms { algorithm_id: TPM_ALG_AES128, enc_scheme: TPM_ES_SYM_CBC_PKCS5PAD, sig_scheme: TPM_SS_NONE, parms: parms.encode() };
+        let mut request = Vec::new();
+        request.extend_from_slice(&(asym_blob.len() as u32).to_be_bytes());
+        request.extend_from_slice(&(sym_blob.len() as u32).to_be_bytes());
+        asym_algorithm.write(&mut request);
+        sym_algorithm.write(&mut request);
+        request.extend_from_slice(&asym_blob);
+        request.extend_from_slice(&sym_blob);
+        request
+    }
+
+    // Recovers the credential the way TPM_ActivateIdentity and TrouSerS do
+    fn activate(ek: &RsaPrivateKey, activation: &Activation) -> (SymmetricKey, [u8; 20], Vec<u8>) {
+        let contents = ek.decrypt(Oaep::new_with_label::<Sha1, _>(OAEP_LABEL), &activation.asym_ca_contents).unwrap();
+        let (session_key, id_digest) = contents.split_at(contents.len() - 20);
+        let session_key = SymmetricKey::parse(session_key).unwrap();
+        let mut reader = BlobReader::new(&activation.sym_ca_attestation);
+        let credential_size = reader.read_u32().unwrap() as usize;
+        let algorithm = KeyParms::read(&mut reader).unwrap();
+        let encrypted_credential = reader.read_bytes(credential_size).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(algorithm.symmetric_parms().unwrap().iv[..], encrypted_credential[..16]);
+        let credential = cbc_decrypt(&session_key, &encrypted_credential[..16], &encrypted_credential[16..]).unwrap();
+        let mut digest = [0; 20];
+        digest.copy_from_slice(id_digest);
+        (session_key, digest, credential)
+    }
+
+    #[test]
+    fn answers_identity_request() {
+        let ca = PrivacyCa::new(key_a());
+        let request = IdentityRequest::parse(&identity_request(&ca, &identity_proof(&ca, EK_CERT))).unwrap();
+        let proof = ca.decrypt_request(&request).unwrap();
+        assert_eq!(proof.label, LABEL);
+        assert_eq!(proof.identity_key, signing_public_key(&key_b()));
+        assert_eq!(proof.endorsement_credential, EK_CERT);
+        assert_eq!(ca.verify_proof(&proof, &ek()), Ok(()));
+
+        let activation = build_activation(&proof.identity_key, &ek(), b"credential", ActivationFormat::AsymCaContents, &mut thread_rng()).unwrap();
+        let (session_key, id_digest, credential) = activate(&key_c(), &activation);
+        assert_eq!(session_key.algorithm_id, TPM_ALG_AES128);
+        assert_eq!(id_digest[..], Sha1::digest(proof.identity_key.to_tpm_pubkey())[..]);
+        assert_eq!(credential, b"credential");
+    }
+
+    #[test]
+    fn builds_ek_blob_activation() {
+        let identity_key = signing_public_key(&key_b());
+        let activation = build_activation(&identity_key, &ek(), b"credential", ActivationFormat::EkBlob, &mut thread_rng()).unwrap();
+        let ek_blob = key_c().decrypt(Oaep::new_with_label::<Sha1, _>(OAEP_LABEL), &activation.asym_ca_contents).unwrap();
+        let mut reader = BlobReader::new(&ek_blob);
+        assert_eq!(reader.read_u16(), Ok(TPM_TAG_EK_BLOB));
+        assert_eq!(reader.read_u16(), Ok(TPM_EK_TYPE_ACTIVATE));
+        let mut activate = BlobReader::new(reader.read_sized_u32().unwrap());
+        assert!(reader.is_empty());
+        assert_eq!(activate.read_u16(), Ok(TPM_TAG_EK_BLOB_ACTIVATE));
+        let session_key = SymmetricKey { algorithm_id: activate.read_u32().unwrap(), enc_scheme: activate.read_u16().unwrap(), data: activate.read_sized_u16().unwrap().to_vec() };
+        assert_eq!(activate.read_digest().unwrap()[..], Sha1::digest(identity_key.to_tpm_pubkey())[..]);
+        let encrypted_credential = &activation.sym_ca_attestation[activation.sym_ca_attestation.len() - 32..];
+        assert_eq!(cbc_decrypt(&session_key, &encrypted_credential[..16], &encrypted_credential[16..]), Ok(b"credential".to_vec()));
+    }
+
+    #[test]
+    fn rejects_missing_endorsement_credential() {
+        let ca = PrivacyCa::new(key_a());
+        let proof = IdentityProof::parse(&identity_proof(&ca, &[])).unwrap();
+        assert_eq!(ca.verify_proof(&proof, &ek()), Err(PrivacyCaError::MissingEndorsementCredential));
+    }
+
+    #[test]
+    fn rejects_credential_for_other_ek() {
+        let ca = PrivacyCa::new(key_a());
+        let proof = IdentityProof::parse(&identity_proof(&ca, EK_CERT)).unwrap();
+        let other_ek = RsaPublicKey::from_rsa(&key_a().to_public_key(), TPM_ES_RSAESOAEP_SHA1_MGF1, TPM_SS_NONE);
+        assert_eq!(ca.verify_proof(&proof, &other_ek), Err(PrivacyCaError::Verify(VerifyError::Mismatch("subjectPublicKey"))));
+    }
+
+    #[test]
+    fn rejects_proof_for_other_ca() {
+        let ca = PrivacyCa::new(key_a());
+        let proof = IdentityProof::parse(&identity_proof(&PrivacyCa::new(key_c()), EK_CERT)).unwrap();
+        assert_eq!(ca.verify_proof(&proof, &ek()), Err(PrivacyCaError::Verify(VerifyError::BadSignature)));
+    }
+
+    #[test]
+    fn rejects_request_for_other_ca() {
+        let ca = PrivacyCa::new(key_a());
+        let request = IdentityRequest::parse(&identity_request(&PrivacyCa::new(key_c()), &identity_proof(&ca, EK_CERT))).unwrap();
+        assert_eq!(ca.decrypt_request(&request), Err(PrivacyCaError::Decrypt));
+    }
+}
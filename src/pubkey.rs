@@ -0,0 +1,130 @@
+use rsa::traits::PublicKeyParts;
+use rsa::{BigUint, Pkcs1v15Sign};
+use sha1::{Digest, Sha1};
+
+use blob::{BlobReader, ParseError};
+use verify::VerifyError;
+
+pub const TPM_ALG_RSA: u32 = 0x00000001;
+
+pub const TPM_ES_NONE: u16 = 0x0001;
+pub const TPM_ES_RSAESPKCSV15: u16 = 0x0002;
+pub const TPM_ES_RSAESOAEP_SHA1_MGF1: u16 = 0x0003;
+
+pub const TPM_SS_NONE: u16 = 0x0001;
+pub const TPM_SS_RSASSAPKCS1V15_SHA1: u16 = 0x0002;
+pub const TPM_SS_RSASSAPKCS1V15_DER: u16 = 0x0003;
+pub const TPM_SS_RSASSAPKCS1V15_INFO: u16 = 0x0004;
+
+const DEFAULT_EXPONENT: [u8; 3] = [0x01, 0x00, 0x01];
+
+/// A `TPM_KEY_PARMS` describing an RSA key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RsaKeyParms {
+    pub algorithm_id: u32,
+    pub enc_scheme: u16,
+    pub sig_scheme: u16,
+    pub key_length: u32,
+    pub num_primes: u32,
+    /// Big-endian public exponent; empty means the default of 65537.
+    pub exponent: Vec<u8>
+}
+
+impl RsaKeyParms {
+    pub(crate) fn read(reader: &mut BlobReader) -> Result<RsaKeyParms, ParseError> {
+        let algorithm_id = reader.read_u32()?;
+        if algorithm_id != TPM_ALG_RSA {
+            return Err(reader.invalid("TPM_KEY_PARMS.algorithmID"));
+        }
+        let enc_scheme = reader.read_u16()?;
+        let sig_scheme = reader.read_u16()?;
+        let mut parms = BlobReader::new(reader.read_sized_u32()?);
+        let key_length = parms.read_u32()?;
+        let num_primes = parms.read_u32()?;
+        let exponent = parms.read_sized_u32()?.to_vec();
+        Ok(RsaKeyParms { algorithm_id, enc_scheme, sig_scheme, key_length, num_primes, exponent })
+    }
+
+    pub fn exponent(&self) -> &[u8] {
+        if self.exponent.is_empty() {
+            &DEFAULT_EXPONENT
+        } else {
+            &self.exponent
+        }
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.algorithm_id.to_be_bytes());
+        out.extend_from_slice(&self.enc_scheme.to_be_bytes());
+        out.extend_from_slice(&self.sig_scheme.to_be_bytes());
+        out.extend_from_slice(&(12 + self.exponent.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.key_length.to_be_bytes());
+        out.extend_from_slice(&self.num_primes.to_be_bytes());
+        out.extend_from_slice(&(self.exponent.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.exponent);
+    }
+}
+
+/// The public half of an RSA key held by a TPM, usable without a TPM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RsaPublicKey {
+    pub key_parms: RsaKeyParms,
+    /// Big-endian modulus.
+    pub modulus: Vec<u8>
+}
+
+impl RsaPublicKey {
+    /// Decodes a `TPM_PUBKEY`, as returned by `TssRsaKey::get_pub_key`.
+    pub fn from_tpm_pubkey(blob: &[u8]) -> Result<RsaPublicKey, ParseError> {
+        let mut reader = BlobReader::new(blob);
+        let key = RsaPublicKey::read(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_PUBKEY"));
+        }
+        Ok(key)
+    }
+
+    /// Wraps a software RSA key, leaving out the exponent if it is the
+    /// default as TPMs do.
+    pub(crate) fn from_rsa(key: &rsa::RsaPublicKey, enc_scheme: u16, sig_scheme: u16) -> RsaPublicKey {
+        let modulus = key.n().to_bytes_be();
+        let mut exponent = key.e().to_bytes_be();
+        if exponent[..] == DEFAULT_EXPONENT[..] {
+            exponent.clear();
+        }
+        let key_parms = RsaKeyParms { algorithm_id: TPM_ALG_RSA, enc_scheme, sig_scheme, key_length: modulus.len() as u32 * 8, num_primes: 2, exponent };
+        RsaPublicKey { key_parms, modulus }
+    }
+
+    /// Encodes the key as a `TPM_PUBKEY`, the inverse of `from_tpm_pubkey`.
+    pub fn to_tpm_pubkey(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.key_parms.write(&mut out);
+        out.extend_from_slice(&(self.modulus.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.modulus);
+        out
+    }
+
+    pub(crate) fn read(reader: &mut BlobReader) -> Result<RsaPublicKey, ParseError> {
+        let key_parms = RsaKeyParms::read(reader)?;
+        let modulus = reader.read_sized_u32()?.to_vec();
+        Ok(RsaPublicKey { key_parms, modulus })
+    }
+
+    pub fn exponent(&self) -> &[u8] {
+        self.key_parms.exponent()
+    }
+
+    pub(crate) fn to_rsa(&self) -> Result<rsa::RsaPublicKey, VerifyError> {
+        rsa::RsaPublicKey::new(BigUint::from_bytes_be(&self.modulus), BigUint::from_bytes_be(self.exponent()))
+            .map_err(|_| VerifyError::InvalidKey)
+    }
+
+    /// Checks a `TPM_SS_RSASSAPKCS1v15_SHA1` signature over `data`.
+    pub fn verify_sha1(&self, data: &[u8], signature: &[u8]) -> Result<(), VerifyError> {
+        let digest = Sha1::digest(data);
+        self.to_rsa()?
+            .verify(Pkcs1v15Sign::new::<Sha1>(), &digest, signature)
+            .map_err(|_| VerifyError::BadSignature)
+    }
+}
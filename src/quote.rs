@@ -0,0 +1,412 @@
+use std::collections::BTreeMap;
+
+use blob::{BlobReader, ParseError, TpmVersion};
+use capability::CapVersionInfo;
+use pubkey::RsaPublicKey;
+use replay::{pcr_composite_hash, ReplayError};
+use verify::VerifyError;
+use TssValidation;
+
+pub const QUOTE_FIXED: [u8; 4] = *b"QUOT";
+pub const QUOTE2_FIXED: [u8; 4] = *b"QUT2";
+
+pub const TPM_TAG_QUOTE_INFO2: u16 = 0x0036;
+
+/// A `TPM_QUOTE_INFO`, the structure signed by `TPM_Quote`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuoteInfo {
+    pub version: TpmVersion,
+    pub fixed: [u8; 4],
+    pub composite_digest: [u8; 20],
+    pub external_data: [u8; 20]
+}
+
+impl QuoteInfo {
+    pub fn parse(data: &[u8]) -> Result<QuoteInfo, ParseError> {
+        let mut reader = BlobReader::new(data);
+        let version = reader.read_version()?;
+        let mut fixed = [0; 4];
+        fixed.copy_from_slice(reader.read_bytes(4)?);
+        let composite_digest = reader.read_digest()?;
+        let external_data = reader.read_digest()?;
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_QUOTE_INFO"));
+        }
+        Ok(QuoteInfo { version, fixed, composite_digest, external_data })
+    }
+}
+
+/// A `TPM_PCR_SELECTION` bitmap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PcrSelection {
+    pub pcr_select: Vec<u8>
+}
+
+impl PcrSelection {
+    pub(crate) fn read(reader: &mut BlobReader) -> Result<PcrSelection, ParseError> {
+        Ok(PcrSelection { pcr_select: reader.read_sized_u16()?.to_vec() })
+    }
+
+    /// Returns the indices of the selected PCRs in ascending order.
+    pub fn pcr_indices(&self) -> Vec<u32> {
+        let mut pcr_indices = Vec::new();
+        for (byte_index, byte) in self.pcr_select.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    pcr_indices.push(byte_index as u32 * 8 + bit);
+                }
+            }
+        }
+        pcr_indices
+    }
+}
+
+/// A `TPM_PCR_INFO_SHORT`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PcrInfoShort {
+    pub pcr_selection: PcrSelection,
+    /// Bitmap of the localities (bit n for locality n) the PCRs were
+    /// released at.
+    pub locality_at_release: u8,
+    pub digest_at_release: [u8; 20]
+}
+
+impl PcrInfoShort {
+    pub(crate) fn read(reader: &mut BlobReader) -> Result<PcrInfoShort, ParseError> {
+        let pcr_selection = PcrSelection::read(reader)?;
+        let locality_at_release = reader.read_u8()?;
+        let digest_at_release = reader.read_digest()?;
+        Ok(PcrInfoShort { pcr_selection, locality_at_release, digest_at_release })
+    }
+}
+
+/// A `TPM_PCR_INFO`, the TPM 1.1 form of a key's PCR binding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PcrInfo {
+    pub pcr_selection: PcrSelection,
+    pub digest_at_release: [u8; 20],
+    pub digest_at_creation: [u8; 20]
+}
+
+impl PcrInfo {
+    pub(crate) fn read(reader: &mut BlobReader) -> Result<PcrInfo, ParseError> {
+        let pcr_selection = PcrSelection::read(reader)?;
+        let digest_at_release = reader.read_digest()?;
+        let digest_at_creation = reader.read_digest()?;
+        Ok(PcrInfo { pcr_selection, digest_at_release, digest_at_creation })
+    }
+}
+
+/// A `TPM_QUOTE_INFO2`, the structure signed by `TPM_Quote2`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuoteInfo2 {
+    pub fixed: [u8; 4],
+    pub external_data: [u8; 20],
+    pub info_short: PcrInfoShort
+}
+
+impl QuoteInfo2 {
+    pub(crate) fn read(reader: &mut BlobReader) -> Result<QuoteInfo2, ParseError> {
+        if reader.read_u16()? != TPM_TAG_QUOTE_INFO2 {
+            return Err(reader.invalid("TPM_QUOTE_INFO2.tag"));
+        }
+        let mut fixed = [0; 4];
+        fixed.copy_from_slice(reader.read_bytes(4)?);
+        let external_data = reader.read_digest()?;
+        let info_short = PcrInfoShort::read(reader)?;
+        Ok(QuoteInfo2 { fixed, external_data, info_short })
+    }
+}
+
+/// The PCR values a verifier expects a quote to cover.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PcrComposite {
+    /// Size of the selection bitmap the TPM uses, usually 2 for TPM 1.1
+    /// and 3 for TPM 1.2.
+    pub size_of_select: u16,
+    pub pcr_values: BTreeMap<u32, Vec<u8>>
+}
+
+impl PcrComposite {
+    pub fn new(size_of_select: u16, pcr_values: BTreeMap<u32, Vec<u8>>) -> PcrComposite {
+        PcrComposite { size_of_select, pcr_values }
+    }
+
+    /// Fails if a PCR index doesn't fit in `size_of_select`.
+    pub fn hash(&self) -> Result<Vec<u8>, ReplayError> {
+        pcr_composite_hash(self.size_of_select, &self.pcr_values)
+    }
+}
+
+/// A signed `TPM_QUOTE_INFO`, verifiable without a TPM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quote {
+    pub quote_info: QuoteInfo,
+    /// The encoded `TPM_QUOTE_INFO` the signature covers.
+    pub data: Vec<u8>,
+    pub signature: Vec<u8>
+}
+
+impl Quote {
+    pub fn new(data: &[u8], signature: &[u8]) -> Result<Quote, ParseError> {
+        Ok(Quote {
+            quote_info: QuoteInfo::parse(data)?,
+            data: data.to_vec(),
+            signature: signature.to_vec()
+        })
+    }
+
+    pub fn from_validation(validation: &TssValidation) -> Result<Quote, ParseError> {
+        Quote::new(&validation.data, &validation.validation_data)
+    }
+
+    /// Checks that the quote was signed by `aik_public_key` and covers
+    /// `expected_nonce` and `expected_pcrs`.
+    pub fn verify(&self, aik_public_key: &RsaPublicKey, expected_nonce: &[u8; 20], expected_pcrs: &PcrComposite) -> Result<(), VerifyError> {
+        aik_public_key.verify_sha1(&self.data, &self.signature)?;
+        if self.quote_info.fixed != QUOTE_FIXED {
+            return Err(VerifyError::Mismatch("TPM_QUOTE_INFO.fixed"));
+        }
+        if self.quote_info.external_data != *expected_nonce {
+            return Err(VerifyError::Mismatch("TPM_QUOTE_INFO.externalData"));
+        }
+        let composite_hash = expected_pcrs.hash().map_err(|_| VerifyError::Mismatch("TPM_PCR_SELECTION"))?;
+        if self.quote_info.composite_digest[..] != composite_hash[..] {
+            return Err(VerifyError::Mismatch("TPM_QUOTE_INFO.digestValue"));
+        }
+        Ok(())
+    }
+}
+
+/// A signed `TPM_QUOTE_INFO2`, optionally followed by the TPM's
+/// `TPM_CAP_VERSION_INFO`, verifiable without a TPM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quote2 {
+    pub quote_info: QuoteInfo2,
+    pub version_info: Option<CapVersionInfo>,
+    /// The encoded structures the signature covers.
+    pub data: Vec<u8>,
+    pub signature: Vec<u8>
+}
+
+impl Quote2 {
+    pub fn new(data: &[u8], signature: &[u8]) -> Result<Quote2, ParseError> {
+        let mut reader = BlobReader::new(data);
+        let quote_info = QuoteInfo2::read(&mut reader)?;
+        let version_info = if reader.is_empty() {
+            None
+        } else {
+            Some(CapVersionInfo::read(&mut reader)?)
+        };
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_QUOTE_INFO2"));
+        }
+        Ok(Quote2 {
+            quote_info,
+            version_info,
+            data: data.to_vec(),
+            signature: signature.to_vec()
+        })
+    }
+
+    pub fn from_validation(validation: &TssValidation) -> Result<Quote2, ParseError> {
+        Quote2::new(&validation.data, &validation.validation_data)
+    }
+
+    /// Checks that the quote was signed by `aik_public_key` and covers
+    /// `expected_nonce` and exactly the PCRs in `expected_pcrs`.
+    ///
+    /// The locality the PCRs were released at is not checked; see
+    /// `quote_info.info_short.locality_at_release`.
+    pub fn verify(&self, aik_public_key: &RsaPublicKey, expected_nonce: &[u8; 20], expected_pcrs: &PcrComposite) -> Result<(), VerifyError> {
+        aik_public_key.verify_sha1(&self.data, &self.signature)?;
+        if self.quote_info.fixed != QUOTE2_FIXED {
+            return Err(VerifyError::Mismatch("TPM_QUOTE_INFO2.fixed"));
+        }
+        if self.quote_info.external_data != *expected_nonce {
+            return Err(VerifyError::Mismatch("TPM_QUOTE_INFO2.externalData"));
+        }
+        let pcr_selection = &self.quote_info.info_short.pcr_selection;
+        let expected_indices: Vec<u32> = expected_pcrs.pcr_values.keys().cloned().collect();
+        if pcr_selection.pcr_indices() != expected_indices {
+            return Err(VerifyError::Mismatch("TPM_PCR_INFO_SHORT.pcrSelection"));
+        }
+        let composite_hash = pcr_composite_hash(pcr_selection.pcr_select.len() as u16, &expected_pcrs.pcr_values)
+            .map_err(|_| VerifyError::Mismatch("TPM_PCR_INFO_SHORT.pcrSelection"))?;
+        if self.quote_info.info_short.digest_at_release[..] != composite_hash[..] {
+            return Err(VerifyError::Mismatch("TPM_PCR_INFO_SHORT.digestAtRelease"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use blob::ParseError;
+    use test_util::{hex, key_a, key_b, sign_sha1, signing_public_key};
+    use verify::VerifyError;
+    use super::*;
+
+    const NONCE: [u8; 20] = [0x4e; 20];
+
+    fn expected_pcrs() -> PcrComposite {
+        let mut pcr_values = BTreeMap::new();
+        pcr_values.insert(0, vec![0x11; 20]);
+        pcr_values.insert(10, vec![0x22; 20]);
+        PcrComposite::new(3, pcr_values)
+    }
+
+    fn quote_info(fixed: &[u8; 4], digest: &[u8], nonce: &[u8; 20]) -> Vec<u8> {
+        let mut data = vec![1, 1, 0, 0];
+        data.extend_from_slice(fixed);
+        data.extend_from_slice(digest);
+        data.extend_from_slice(nonce);
+        data
+    }
+
+    fn signed_quote(data: &[u8]) -> Quote {
+        Quote::new(data, &sign_sha1(&key_a(), data)).unwrap()
+    }
+
+    fn quote_info2(pcr_select: &[u8], digest: &[u8], nonce: &[u8; 20]) -> Vec<u8> {
+        let mut data = TPM_TAG_QUOTE_INFO2.to_be_bytes().to_vec();
+        data.extend_from_slice(&QUOTE2_FIXED);
+        data.extend_from_slice(nonce);
+        data.extend_from_slice(&(pcr_select.len() as u16).to_be_bytes());
+        data.extend_from_slice(pcr_select);
+        // Released at locality 0
+        data.push(0x01);
+        data.extend_from_slice(digest);
+        data
+    }
+
+    fn signed_quote2(data: &[u8]) -> Quote2 {
+        Quote2::new(data, &sign_sha1(&key_a(), data)).unwrap()
+    }
+
+    #[test]
+    fn parses_quote_info() {
+        let digest = expected_pcrs().hash().unwrap();
+        let quote_info = QuoteInfo::parse(&quote_info(&QUOTE_FIXED, &digest, &NONCE)).unwrap();
+        assert_eq!(quote_info.version, TpmVersion { major: 1, minor: 1, rev_major: 0, rev_minor: 0 });
+        assert_eq!(quote_info.fixed, QUOTE_FIXED);
+        assert_eq!(quote_info.composite_digest[..], digest[..]);
+        assert_eq!(quote_info.external_data, NONCE);
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let mut data = quote_info(&QUOTE_FIXED, &[0; 20], &NONCE);
+        data.push(0);
+        assert_eq!(QuoteInfo::parse(&data), Err(ParseError::InvalidValue { offset: 48, field: "TPM_QUOTE_INFO" }));
+    }
+
+    #[test]
+    fn verifies_quote() {
+        let quote = signed_quote(&quote_info(&QUOTE_FIXED, &expected_pcrs().hash().unwrap(), &NONCE));
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &expected_pcrs()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_other_key() {
+        let quote = signed_quote(&quote_info(&QUOTE_FIXED, &expected_pcrs().hash().unwrap(), &NONCE));
+        assert_eq!(quote.verify(&signing_public_key(&key_b()), &NONCE, &expected_pcrs()), Err(VerifyError::BadSignature));
+    }
+
+    #[test]
+    fn rejects_modified_data() {
+        let mut quote = signed_quote(&quote_info(&QUOTE_FIXED, &expected_pcrs().hash().unwrap(), &NONCE));
+        quote.data[30] ^= 1;
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &expected_pcrs()), Err(VerifyError::BadSignature));
+    }
+
+    #[test]
+    fn rejects_quote2_fixed() {
+        let quote = signed_quote(&quote_info(&QUOTE2_FIXED, &expected_pcrs().hash().unwrap(), &NONCE));
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &expected_pcrs()), Err(VerifyError::Mismatch("TPM_QUOTE_INFO.fixed")));
+    }
+
+    #[test]
+    fn rejects_other_nonce() {
+        let quote = signed_quote(&quote_info(&QUOTE_FIXED, &expected_pcrs().hash().unwrap(), &NONCE));
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &[0; 20], &expected_pcrs()), Err(VerifyError::Mismatch("TPM_QUOTE_INFO.externalData")));
+    }
+
+    #[test]
+    fn rejects_other_pcr_values() {
+        let quote = signed_quote(&quote_info(&QUOTE_FIXED, &expected_pcrs().hash().unwrap(), &NONCE));
+        let mut pcrs = expected_pcrs();
+        pcrs.pcr_values.insert(10, vec![0x23; 20]);
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &pcrs), Err(VerifyError::Mismatch("TPM_QUOTE_INFO.digestValue")));
+    }
+
+    #[test]
+    fn rejects_out_of_range_pcrs() {
+        let quote = signed_quote(&quote_info(&QUOTE_FIXED, &expected_pcrs().hash().unwrap(), &NONCE));
+        let mut pcrs = expected_pcrs();
+        pcrs.size_of_select = 1;
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &pcrs), Err(VerifyError::Mismatch("TPM_PCR_SELECTION")));
+    }
+
+    #[test]
+    fn parses_quote2_with_version_info() {
+        let mut data = quote_info2(&[0x01, 0x04, 0x00], &expected_pcrs().hash().unwrap(), &NONCE);
+        data.extend_from_slice(&hex("003001020074000200494658000000"));
+        let quote = signed_quote2(&data);
+        assert_eq!(quote.quote_info.info_short.pcr_selection.pcr_indices(), vec![0, 10]);
+        assert_eq!(quote.quote_info.info_short.locality_at_release, 0x01);
+        assert_eq!(quote.version_info.as_ref().map(|version_info| version_info.tpm_vendor_id), Some(*b"IFX\0"));
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &expected_pcrs()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_quote2_trailing_data() {
+        let mut data = quote_info2(&[0x01, 0x04, 0x00], &[0; 20], &NONCE);
+        data.extend_from_slice(&hex("003001020074000200494658000000"));
+        data.push(0);
+        assert_eq!(Quote2::new(&data, &[]), Err(ParseError::InvalidValue { offset: 67, field: "TPM_QUOTE_INFO2" }));
+    }
+
+    #[test]
+    fn verifies_quote2() {
+        let quote = signed_quote2(&quote_info2(&[0x01, 0x04, 0x00], &expected_pcrs().hash().unwrap(), &NONCE));
+        assert_eq!(quote.version_info, None);
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &expected_pcrs()), Ok(()));
+    }
+
+    #[test]
+    fn verifies_quote2_with_tpm_1_1_selection_size() {
+        let mut pcrs = expected_pcrs();
+        pcrs.size_of_select = 2;
+        let quote = signed_quote2(&quote_info2(&[0x01, 0x04], &pcrs.hash().unwrap(), &NONCE));
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &expected_pcrs()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_quote2_other_key() {
+        let quote = signed_quote2(&quote_info2(&[0x01, 0x04, 0x00], &expected_pcrs().hash().unwrap(), &NONCE));
+        assert_eq!(quote.verify(&signing_public_key(&key_b()), &NONCE, &expected_pcrs()), Err(VerifyError::BadSignature));
+    }
+
+    #[test]
+    fn rejects_quote2_other_nonce() {
+        let quote = signed_quote2(&quote_info2(&[0x01, 0x04, 0x00], &expected_pcrs().hash().unwrap(), &NONCE));
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &[0; 20], &expected_pcrs()), Err(VerifyError::Mismatch("TPM_QUOTE_INFO2.externalData")));
+    }
+
+    #[test]
+    fn rejects_quote2_other_selection() {
+        let quote = signed_quote2(&quote_info2(&[0x01, 0x00, 0x00], &expected_pcrs().hash().unwrap(), &NONCE));
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &expected_pcrs()), Err(VerifyError::Mismatch("TPM_PCR_INFO_SHORT.pcrSelection")));
+    }
+
+    #[test]
+    fn rejects_quote2_other_pcr_values() {
+        let quote = signed_quote2(&quote_info2(&[0x01, 0x04, 0x00], &expected_pcrs().hash().unwrap(), &NONCE));
+        let mut pcrs = expected_pcrs();
+        pcrs.pcr_values.insert(0, vec![0x12; 20]);
+        assert_eq!(quote.verify(&signing_public_key(&key_a()), &NONCE, &pcrs), Err(VerifyError::Mismatch("TPM_PCR_INFO_SHORT.digestAtRelease")));
+    }
+}
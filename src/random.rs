@@ -0,0 +1,96 @@
+use std::cmp;
+use std::io;
+
+#[cfg(feature = "rand_core")]
+use rand_core::{CryptoRng, RngCore};
+
+use memory::wipe;
+use TssTPM;
+
+/// Bytes requested from the TPM at a time by default.
+pub const DEFAULT_BATCH_SIZE: usize = 128;
+
+/// Reads random bytes from the TPM's generator, fetching them in batches.
+///
+/// Implements `io::Read`, and `rand_core::RngCore` with the `rand_core`
+/// feature. Buffered bytes are wiped when the reader is dropped.
+pub struct TpmRng<'tpm, 'context: 'tpm> {
+    tpm: &'tpm TssTPM<'context>,
+    batch_size: usize,
+    buffer: Vec<u8>,
+    position: usize
+}
+
+impl<'tpm, 'context> TpmRng<'tpm, 'context> {
+    pub fn new(tpm: &'tpm TssTPM<'context>) -> TpmRng<'tpm, 'context> {
+        TpmRng::with_batch_size(tpm, DEFAULT_BATCH_SIZE)
+    }
+
+    pub fn with_batch_size(tpm: &'tpm TssTPM<'context>, batch_size: usize) -> TpmRng<'tpm, 'context> {
+        TpmRng { tpm, batch_size: cmp::max(batch_size, 1), buffer: Vec::new(), position: 0 }
+    }
+
+    fn refill(&mut self) -> io::Result<()> {
+        let batch = self.tpm.get_random(self.batch_size)
+            .map_err(io::Error::other)?;
+        if batch.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "TPM returned no random bytes"));
+        }
+        wipe(&mut self.buffer);
+        self.buffer = batch;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<'tpm, 'context> io::Read for TpmRng<'tpm, 'context> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.position == self.buffer.len() {
+            self.refill()?;
+        }
+        let count = cmp::min(buf.len(), self.buffer.len() - self.position);
+        let end = self.position + count;
+        buf[..count].copy_from_slice(&self.buffer[self.position..end]);
+        wipe(&mut self.buffer[self.position..end]);
+        self.position = end;
+        Ok(count)
+    }
+}
+
+impl<'tpm, 'context> Drop for TpmRng<'tpm, 'context> {
+    fn drop(&mut self) {
+        wipe(&mut self.buffer);
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl<'tpm, 'context> RngCore for TpmRng<'tpm, 'context> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_ne_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_ne_bytes(bytes)
+    }
+
+    /// Panics if the TPM fails; use `try_fill_bytes` to handle that.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(error) = self.try_fill_bytes(dest) {
+            panic!("TPM random number generator failed: {}", error);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        io::Read::read_exact(self, dest).map_err(rand_core::Error::new)
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl<'tpm, 'context> CryptoRng for TpmRng<'tpm, 'context> {}
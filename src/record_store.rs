@@ -0,0 +1,394 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+use crc32fast::Hasher;
+
+use blob::{BlobReader, ParseError};
+use {TssError, TssNvStore};
+
+pub const RECORD_SLOT_MAGIC: [u8; 4] = *b"TRKV";
+pub const RECORD_SLOT_FORMAT: u16 = 1;
+
+/// Length of the header at the start of each slot.
+pub const RECORD_SLOT_HEADER_LENGTH: usize = 24;
+
+/// Error returned by `NvRecordStore`.
+#[derive(Debug)]
+pub enum RecordStoreError {
+    Tss(TssError),
+    /// A slot's header can't be decoded, or the newest copy of the store
+    /// fails its checksum.
+    Corrupted { slot: usize },
+    /// The newest copy is older than one already seen, so NV has been
+    /// rolled back.
+    Stale { generation: u64, min_generation: u64 },
+    /// The records don't fit in a slot.
+    TooLarge { size: usize, capacity: usize },
+    /// A record's key or value is longer than 65535 bytes.
+    RecordTooLarge { key: String }
+}
+
+impl fmt::Display for RecordStoreError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecordStoreError::Tss(ref error) => write!(fmt, "NV access failed: {}", error),
+            RecordStoreError::Corrupted { slot } => write!(fmt, "record slot {} is corrupted", slot),
+            RecordStoreError::Stale { generation, min_generation } =>
+                write!(fmt, "record store generation {} is older than generation {} already seen", generation, min_generation),
+            RecordStoreError::TooLarge { size, capacity } =>
+                write!(fmt, "records need {} bytes but a slot holds {}", size, capacity),
+            RecordStoreError::RecordTooLarge { ref key } =>
+                write!(fmt, "record {:?} is too large to store", key)
+        }
+    }
+}
+
+impl error::Error for RecordStoreError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            RecordStoreError::Tss(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<TssError> for RecordStoreError {
+    fn from(error: TssError) -> RecordStoreError {
+        RecordStoreError::Tss(error)
+    }
+}
+
+/// One copy of the store's records.
+///
+/// Encoded as a big-endian header (magic, format, reserved, generation,
+/// payload length, CRC-32 of everything else) followed by the records,
+/// each a 16-bit sized key and a 16-bit sized value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordSlot {
+    pub generation: u64,
+    pub records: BTreeMap<String, Vec<u8>>
+}
+
+impl RecordSlot {
+    pub fn encode(&self) -> Result<Vec<u8>, RecordStoreError> {
+        let mut payload = Vec::new();
+        for (key, value) in &self.records {
+            if key.len() > u16::MAX as usize || value.len() > u16::MAX as usize {
+                return Err(RecordStoreError::RecordTooLarge { key: key.clone() });
+            }
+            payload.extend_from_slice(&(key.len() as u16).to_be_bytes());
+            payload.extend_from_slice(key.as_bytes());
+            payload.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            payload.extend_from_slice(value);
+        }
+        let mut out = Vec::with_capacity(RECORD_SLOT_HEADER_LENGTH + payload.len());
+        out.extend_from_slice(&RECORD_SLOT_MAGIC);
+        out.extend_from_slice(&RECORD_SLOT_FORMAT.to_be_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&self.generation.to_be_bytes());
+        out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        let crc = slot_crc(&out, &payload);
+        out.extend_from_slice(&crc.to_be_bytes());
+        out.extend_from_slice(&payload);
+        Ok(out)
+    }
+
+    /// Decodes a slot header, returning the generation and payload length,
+    /// or `None` if the slot has never been written.
+    pub fn decode_header(header: &[u8]) -> Result<Option<(u64, usize)>, ParseError> {
+        if header.iter().all(|&byte| byte == 0xff) || header.iter().all(|&byte| byte == 0) {
+            return Ok(None);
+        }
+        let mut reader = BlobReader::new(header);
+        if reader.read_bytes(4)? != RECORD_SLOT_MAGIC {
+            return Err(reader.invalid("record slot magic"));
+        }
+        if reader.read_u16()? != RECORD_SLOT_FORMAT {
+            return Err(reader.invalid("record slot format"));
+        }
+        reader.read_u16()?;
+        let generation = reader.read_u64()?;
+        let payload_length = reader.read_u32()? as usize;
+        Ok(Some((generation, payload_length)))
+    }
+
+    /// Decodes a whole slot, checking its CRC. Returns `None` if the slot
+    /// has never been written.
+    pub fn decode(data: &[u8]) -> Result<Option<RecordSlot>, ParseError> {
+        let mut reader = BlobReader::new(data);
+        let header = reader.read_bytes(RECORD_SLOT_HEADER_LENGTH)?;
+        let (generation, payload_length) = match RecordSlot::decode_header(header)? {
+            Some(header) => header,
+            None => return Ok(None)
+        };
+        let payload = reader.read_bytes(payload_length)?;
+        let mut crc_bytes = [0; 4];
+        crc_bytes.copy_from_slice(&header[20..]);
+        if slot_crc(&header[..20], payload) != u32::from_be_bytes(crc_bytes) {
+            return Err(ParseError::InvalidValue { offset: 20, field: "record slot CRC" });
+        }
+        let mut payload = BlobReader::new(payload);
+        let mut records = BTreeMap::new();
+        while !payload.is_empty() {
+            let key = String::from_utf8(payload.read_sized_u16()?.to_vec())
+                .map_err(|_| payload.invalid("record key"))?;
+            let value = payload.read_sized_u16()?.to_vec();
+            records.insert(key, value);
+        }
+        Ok(Some(RecordSlot { generation, records }))
+    }
+}
+
+fn slot_crc(header: &[u8], payload: &[u8]) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(header);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// Finds and decodes the newest copy among two slots of `slot_sizes`
+/// bytes. `read_slot(index, length)` reads the first `length` bytes of
+/// slot `index`.
+fn find_newest<F>(slot_sizes: [u32; 2], min_generation: u64, mut read_slot: F) -> Result<Option<(usize, RecordSlot)>, RecordStoreError>
+    where F: FnMut(usize, usize) -> Result<Vec<u8>, TssError>
+{
+    let mut newest: Option<(usize, u64, usize)> = None;
+    for index in 0..slot_sizes.len() {
+        let header = read_slot(index, RECORD_SLOT_HEADER_LENGTH)?;
+        match RecordSlot::decode_header(&header) {
+            Ok(Some((generation, payload_length))) => {
+                if newest.is_none_or(|(_, newest_generation, _)| generation > newest_generation) {
+                    newest = Some((index, generation, payload_length));
+                }
+            },
+            Ok(None) => {},
+            // The slot might hold the newest copy, so don't fall back to
+            // the other one
+            Err(_) => return Err(RecordStoreError::Corrupted { slot: index })
+        }
+    }
+    let (index, generation, payload_length) = match newest {
+        Some(newest) => newest,
+        None if min_generation > 0 => return Err(RecordStoreError::Stale { generation: 0, min_generation }),
+        None => return Ok(None)
+    };
+    if generation < min_generation {
+        return Err(RecordStoreError::Stale { generation, min_generation });
+    }
+    if RECORD_SLOT_HEADER_LENGTH + payload_length > slot_sizes[index] as usize {
+        return Err(RecordStoreError::Corrupted { slot: index });
+    }
+    let data = read_slot(index, RECORD_SLOT_HEADER_LENGTH + payload_length)?;
+    match RecordSlot::decode(&data) {
+        Ok(Some(record_slot)) => Ok(Some((index, record_slot))),
+        _ => Err(RecordStoreError::Corrupted { slot: index })
+    }
+}
+
+/// Part of an NV area holding one copy of an `NvRecordStore`.
+pub struct NvSlot<'nv, 'context: 'nv> {
+    pub nv_store: &'nv TssNvStore<'context>,
+    pub offset: u32,
+    pub size: u32
+}
+
+/// A small key-value store kept in TPM NV, for configuration secrets.
+///
+/// Two slots hold copies of the records, each tagged with a generation.
+/// Updates go to the slot not holding the newest copy, payload first and
+/// header last in a single NV write, so an interrupted update leaves the
+/// previous copy in force. The newest copy is never silently skipped: if
+/// a slot's header can't be decoded, or the newest copy fails its CRC,
+/// reads fail.
+///
+/// NV can't be rolled back by anyone unable to write it, so define the
+/// areas with `OWNER_WRITE` or `AUTH_WRITE`. As a further check, the store
+/// refuses copies older than the newest generation it has seen; persist
+/// `generation` and pass it to `set_min_generation` to extend this across
+/// restarts.
+pub struct NvRecordStore<'nv, 'context: 'nv> {
+    slots: [NvSlot<'nv, 'context>; 2],
+    min_generation: Cell<u64>
+}
+
+impl<'nv, 'context> NvRecordStore<'nv, 'context> {
+    pub fn new(first: NvSlot<'nv, 'context>, second: NvSlot<'nv, 'context>) -> NvRecordStore<'nv, 'context> {
+        NvRecordStore { slots: [first, second], min_generation: Cell::new(0) }
+    }
+
+    /// Splits `size` bytes at `offset` of one NV area into the two slots.
+    pub fn in_area(nv_store: &'nv TssNvStore<'context>, offset: u32, size: u32) -> NvRecordStore<'nv, 'context> {
+        let half = size / 2;
+        NvRecordStore::new(NvSlot { nv_store, offset, size: half }, NvSlot { nv_store, offset: offset + half, size: half })
+    }
+
+    /// The newest generation read or written so far.
+    pub fn generation(&self) -> u64 {
+        self.min_generation.get()
+    }
+
+    pub fn set_min_generation(&self, generation: u64) {
+        self.min_generation.set(generation);
+    }
+
+    /// Reads the newest copy of the records.
+    pub fn load(&self) -> Result<BTreeMap<String, Vec<u8>>, RecordStoreError> {
+        Ok(self.load_newest()?.map_or_else(BTreeMap::new, |(_, slot)| slot.records))
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, RecordStoreError> {
+        Ok(self.load()?.remove(key))
+    }
+
+    pub fn insert(&self, key: &str, value: &[u8]) -> Result<(), RecordStoreError> {
+        let mut records = self.load()?;
+        records.insert(key.to_string(), value.to_vec());
+        self.save(&records)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<Option<Vec<u8>>, RecordStoreError> {
+        let mut records = self.load()?;
+        let value = records.remove(key);
+        if value.is_some() {
+            self.save(&records)?;
+        }
+        Ok(value)
+    }
+
+    /// Replaces all records with `records`, as the next generation.
+    pub fn save(&self, records: &BTreeMap<String, Vec<u8>>) -> Result<(), RecordStoreError> {
+        let (target, generation) = match self.load_newest()? {
+            Some((newest, slot)) => (1 - newest, slot.generation + 1),
+            None => (0, self.min_generation.get() + 1)
+        };
+        let encoded = RecordSlot { generation, records: records.clone() }.encode()?;
+        let slot = &self.slots[target];
+        if encoded.len() > slot.size as usize {
+            return Err(RecordStoreError::TooLarge { size: encoded.len(), capacity: slot.size as usize });
+        }
+        let (header, payload) = encoded.split_at(RECORD_SLOT_HEADER_LENGTH);
+        slot.nv_store.write(slot.offset + RECORD_SLOT_HEADER_LENGTH as u32, payload)?;
+        slot.nv_store.write_value(slot.offset, header)?;
+        self.min_generation.set(generation);
+        Ok(())
+    }
+
+    fn load_newest(&self) -> Result<Option<(usize, RecordSlot)>, RecordStoreError> {
+        let slot_sizes = [self.slots[0].size, self.slots[1].size];
+        let newest = find_newest(slot_sizes, self.min_generation.get(), |index, length| {
+            let slot = &self.slots[index];
+            slot.nv_store.read(slot.offset, length)
+        })?;
+        if let Some((_, ref record_slot)) = newest {
+            self.min_generation.set(record_slot.generation);
+        }
+        Ok(newest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blob::ParseError;
+    use super::*;
+
+    const SLOT_SIZE: u32 = 128;
+
+    fn record_slot(generation: u64) -> RecordSlot {
+        let mut records = BTreeMap::new();
+        records.insert("api-key".to_string(), b"secret".to_vec());
+        records.insert("generation".to_string(), generation.to_be_bytes().to_vec());
+        RecordSlot { generation, records }
+    }
+
+    fn blank_slot() -> Vec<u8> {
+        vec![0xff; SLOT_SIZE as usize]
+    }
+
+    fn written_slot(encoded: &[u8]) -> Vec<u8> {
+        let mut slot = blank_slot();
+        slot[..encoded.len()].copy_from_slice(encoded);
+        slot
+    }
+
+    fn find_newest_in(slots: &[Vec<u8>; 2], min_generation: u64) -> Result<Option<(usize, RecordSlot)>, RecordStoreError> {
+        find_newest([SLOT_SIZE; 2], min_generation, |index, length| Ok(slots[index][..length].to_vec()))
+    }
+
+    #[test]
+    fn round_trips_slot() {
+        let encoded = record_slot(7).encode().unwrap();
+        assert_eq!(&encoded[..4], b"TRKV");
+        assert_eq!(RecordSlot::decode(&encoded), Ok(Some(record_slot(7))));
+        assert_eq!(RecordSlot::decode(&blank_slot()), Ok(None));
+    }
+
+    #[test]
+    fn rejects_bad_crc() {
+        let mut encoded = record_slot(7).encode().unwrap();
+        let last = encoded.len() - 1;
+        encoded[last] ^= 1;
+        assert_eq!(RecordSlot::decode(&encoded), Err(ParseError::InvalidValue { offset: 20, field: "record slot CRC" }));
+        match find_newest_in(&[written_slot(&encoded), blank_slot()], 0) {
+            Err(RecordStoreError::Corrupted { slot: 0 }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_payload() {
+        let encoded = record_slot(7).encode().unwrap();
+        assert_eq!(RecordSlot::decode(&encoded[..encoded.len() - 1]), Err(ParseError::UnexpectedEnd { offset: RECORD_SLOT_HEADER_LENGTH }));
+        // A payload length running past the end of the slot
+        let mut header = encoded[..RECORD_SLOT_HEADER_LENGTH].to_vec();
+        header[16..20].copy_from_slice(&SLOT_SIZE.to_be_bytes());
+        match find_newest_in(&[blank_slot(), written_slot(&header)], 0) {
+            Err(RecordStoreError::Corrupted { slot: 1 }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_header() {
+        let mut encoded = record_slot(8).encode().unwrap();
+        encoded[0] = b'X';
+        let slots = [written_slot(&encoded), written_slot(&record_slot(7).encode().unwrap())];
+        match find_newest_in(&slots, 0) {
+            Err(RecordStoreError::Corrupted { slot: 0 }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_stale_generation() {
+        let slots = [written_slot(&record_slot(3).encode().unwrap()), written_slot(&record_slot(4).encode().unwrap())];
+        match find_newest_in(&slots, 5) {
+            Err(RecordStoreError::Stale { generation: 4, min_generation: 5 }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+        match find_newest_in(&[blank_slot(), blank_slot()], 5) {
+            Err(RecordStoreError::Stale { generation: 0, min_generation: 5 }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn chooses_newest_slot() {
+        let older = written_slot(&record_slot(3).encode().unwrap());
+        let newer = written_slot(&record_slot(4).encode().unwrap());
+        assert_eq!(find_newest_in(&[older.clone(), newer.clone()], 4).unwrap(), Some((1, record_slot(4))));
+        assert_eq!(find_newest_in(&[newer, older], 0).unwrap(), Some((0, record_slot(4))));
+        assert_eq!(find_newest_in(&[blank_slot(), blank_slot()], 0).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_oversized_record() {
+        let mut records = BTreeMap::new();
+        records.insert("big".to_string(), vec![0; u16::MAX as usize + 1]);
+        match (RecordSlot { generation: 1, records }).encode() {
+            Err(RecordStoreError::RecordTooLarge { ref key }) if key == "big" => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+}
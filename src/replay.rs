@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+use sha1::{Digest, Sha1};
+
+use event_log::{EventType, PcrEvent};
+
+pub const PCR_VALUE_LENGTH: usize = 20;
+
+/// Returns `SHA1(pcr_value || digest)`, the value a PCR holds after `digest`
+/// is extended into it.
+pub fn sha1_extend(pcr_value: &[u8], digest: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(pcr_value);
+    hasher.update(digest);
+    hasher.finalize().to_vec()
+}
+
+/// Returns the SHA1 of the `TPM_PCR_COMPOSITE` selecting exactly the PCRs in
+/// `pcr_values`, which is what a quote signs over.
+///
+/// `size_of_select` is the size of the selection bitmap used by the TPM,
+/// usually 2 for TPM 1.1 and 3 for TPM 1.2. A PCR index too large for it
+/// is an error.
+pub fn pcr_composite_hash(size_of_select: u16, pcr_values: &BTreeMap<u32, Vec<u8>>) -> Result<Vec<u8>, ReplayError> {
+    let mut select = vec![0u8; size_of_select as usize];
+    let mut values = Vec::new();
+    for (pcr_index, value) in pcr_values {
+        match select.get_mut(*pcr_index as usize / 8) {
+            Some(byte) => *byte |= 1 << (*pcr_index % 8),
+            None => return Err(ReplayError::PcrOutOfRange { pcr_index: *pcr_index, size_of_select })
+        }
+        values.extend_from_slice(value);
+    }
+    let mut hasher = Sha1::new();
+    hasher.update([(size_of_select >> 8) as u8, size_of_select as u8]);
+    hasher.update(&select);
+    hasher.update((values.len() as u32).to_be_bytes());
+    hasher.update(&values);
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Software model of the PCRs, built up by replaying an event log.
+#[derive(Clone, Debug, Default)]
+pub struct PcrReplay {
+    pcr_values: BTreeMap<u32, Vec<u8>>
+}
+
+impl PcrReplay {
+    pub fn new() -> PcrReplay {
+        PcrReplay { pcr_values: BTreeMap::new() }
+    }
+
+    /// Sets the value `pcr_index` starts from. PCRs default to all zeroes,
+    /// which is not true of the dynamic PCRs (17-22) before a late launch.
+    pub fn set_initial_value(&mut self, pcr_index: u32, pcr_value: &[u8]) {
+        self.pcr_values.insert(pcr_index, pcr_value.to_vec());
+    }
+
+    /// Extends the digest recorded in `event` into its PCR. `EV_NO_ACTION`
+    /// events are informational only and are skipped.
+    pub fn extend(&mut self, event: &PcrEvent) {
+        if event.event_type == EventType::NoAction {
+            return;
+        }
+        let new_value = sha1_extend(&self.pcr_value(event.pcr_index), &event.pcr_value);
+        self.pcr_values.insert(event.pcr_index, new_value);
+    }
+
+    pub fn pcr_value(&self, pcr_index: u32) -> Vec<u8> {
+        match self.pcr_values.get(&pcr_index) {
+            Some(value) => value.clone(),
+            None => vec![0; PCR_VALUE_LENGTH]
+        }
+    }
+
+    /// Returns every PCR touched by the replay so far.
+    pub fn pcr_values(&self) -> &BTreeMap<u32, Vec<u8>> {
+        &self.pcr_values
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The replayed value of `pcr_index` doesn't match the expected value.
+    ///
+    /// `event_index` is the position in the log of the first event that
+    /// diverges: the event following the last point where the replay held
+    /// the expected value, or the first event for the PCR if it never did.
+    /// It is `None` if the log has no events for the PCR at all.
+    PcrMismatch {
+        pcr_index: u32,
+        event_index: Option<usize>,
+        expected: Vec<u8>,
+        replayed: Vec<u8>
+    },
+    /// The composite hash of the replayed PCRs doesn't match the quoted one.
+    /// A composite can't be split back into PCRs, so the divergent event
+    /// can't be located.
+    CompositeMismatch {
+        expected: Vec<u8>,
+        replayed: Vec<u8>
+    },
+    /// `pcr_index` doesn't fit in a selection bitmap of `size_of_select`
+    /// bytes.
+    PcrOutOfRange {
+        pcr_index: u32,
+        size_of_select: u16
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::PcrMismatch { pcr_index, event_index: Some(event_index), .. } =>
+                write!(fmt, "PCR {} diverges from the event log at event {}", pcr_index, event_index),
+            ReplayError::PcrMismatch { pcr_index, event_index: None, .. } =>
+                write!(fmt, "PCR {} has no events in the event log but is not in its initial state", pcr_index),
+            ReplayError::CompositeMismatch { .. } =>
+                write!(fmt, "PCR composite hash does not match the event log"),
+            ReplayError::PcrOutOfRange { pcr_index, size_of_select } =>
+                write!(fmt, "PCR {} is out of range for a {} byte PCR selection", pcr_index, size_of_select)
+        }
+    }
+}
+
+impl error::Error for ReplayError {}
+
+/// Replays `events` and checks the result against `expected`, typically a
+/// set of `TssTPM::pcr_read` values. PCRs not in `expected` are not checked.
+///
+/// On mismatch, the error for the earliest divergent event is returned.
+pub fn verify_event_log(events: &[PcrEvent], expected: &BTreeMap<u32, Vec<u8>>) -> Result<PcrReplay, ReplayError> {
+    let mut replay = PcrReplay::new();
+    // Track where each PCR was first extended and where it last held its
+    // expected value, so a mismatch can be pinned to an event
+    let mut first_event: BTreeMap<u32, usize> = BTreeMap::new();
+    let mut last_match: BTreeMap<u32, usize> = BTreeMap::new();
+    for (event_index, event) in events.iter().enumerate() {
+        replay.extend(event);
+        if event.event_type == EventType::NoAction {
+            continue;
+        }
+        first_event.entry(event.pcr_index).or_insert(event_index);
+        if let Some(expected_value) = expected.get(&event.pcr_index) {
+            if replay.pcr_value(event.pcr_index) == *expected_value {
+                last_match.insert(event.pcr_index, event_index);
+            }
+        }
+    }
+
+    let mut first_mismatch: Option<ReplayError> = None;
+    let mut first_mismatch_index = usize::MAX;
+    for (pcr_index, expected_value) in expected {
+        let replayed = replay.pcr_value(*pcr_index);
+        if replayed == *expected_value {
+            continue;
+        }
+        let event_index = match last_match.get(pcr_index) {
+            Some(matched) => events.iter().enumerate()
+                .skip(matched + 1)
+                .find(|&(_, event)| event.pcr_index == *pcr_index && event.event_type != EventType::NoAction)
+                .map(|(event_index, _)| event_index),
+            None => first_event.get(pcr_index).cloned()
+        };
+        let sort_index = event_index.unwrap_or(usize::MAX);
+        if first_mismatch.is_none() || sort_index < first_mismatch_index {
+            first_mismatch_index = sort_index;
+            first_mismatch = Some(ReplayError::PcrMismatch {
+                pcr_index: *pcr_index,
+                event_index,
+                expected: expected_value.clone(),
+                replayed
+            });
+        }
+    }
+    match first_mismatch {
+        Some(error) => Err(error),
+        None => Ok(replay)
+    }
+}
+
+/// Replays `events` and checks the PCRs in `pcr_indices` against a quoted
+/// composite hash, such as the digest in a `TPM_QUOTE_INFO`.
+pub fn verify_event_log_composite(events: &[PcrEvent], size_of_select: u16, pcr_indices: &[u32], composite_hash: &[u8]) -> Result<PcrReplay, ReplayError> {
+    let mut replay = PcrReplay::new();
+    for event in events {
+        replay.extend(event);
+    }
+    let mut selected = BTreeMap::new();
+    for pcr_index in pcr_indices {
+        selected.insert(*pcr_index, replay.pcr_value(*pcr_index));
+    }
+    let replayed = pcr_composite_hash(size_of_select, &selected)?;
+    if replayed != composite_hash {
+        return Err(ReplayError::CompositeMismatch { expected: composite_hash.to_vec(), replayed });
+    }
+    Ok(replay)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use trousers_sys::tspi::TSS_VERSION;
+
+    use event_log::{EventType, PcrEvent};
+    use test_util::hex;
+    use super::*;
+
+    fn event(pcr_index: u32, event_type: EventType, digest: u8) -> PcrEvent {
+        let mut event = PcrEvent::new(TSS_VERSION::default(), pcr_index, event_type, &[]);
+        event.pcr_value = vec![digest; 20];
+        event
+    }
+
+    #[test]
+    fn sha1_extend_hashes_value_then_digest() {
+        assert_eq!(sha1_extend(&[0; 20], &[1; 20]), hex("c3ad7f64b8d976aaf2b3a9c98f7ee5631cde7125"));
+    }
+
+    #[test]
+    fn pcr_composite_hash_encodes_tpm_pcr_composite() {
+        let mut pcr_values = BTreeMap::new();
+        pcr_values.insert(0, vec![0; 20]);
+        assert_eq!(pcr_composite_hash(3, &pcr_values).unwrap(), hex("cd453166fb4dc0203f003542f944b9d469ddb1f9"));
+    }
+
+    #[test]
+    fn pcr_composite_hash_rejects_pcr_outside_selection() {
+        let mut pcr_values = BTreeMap::new();
+        pcr_values.insert(24, vec![0; 20]);
+        match pcr_composite_hash(3, &pcr_values) {
+            Err(ReplayError::PcrOutOfRange { pcr_index: 24, size_of_select: 3 }) => {},
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn verify_event_log_accepts_matching_log() {
+        let events = vec![event(0, EventType::PostCode, 1), event(0, EventType::NoAction, 9), event(0, EventType::Separator, 2), event(4, EventType::Separator, 3)];
+        let mut expected = BTreeMap::new();
+        expected.insert(0, sha1_extend(&sha1_extend(&[0; 20], &[1; 20]), &[2; 20]));
+        expected.insert(4, sha1_extend(&[0; 20], &[3; 20]));
+        let replay = verify_event_log(&events, &expected).unwrap();
+        assert_eq!(replay.pcr_values(), &expected);
+    }
+
+    #[test]
+    fn verify_event_log_reports_first_divergent_event() {
+        let events = vec![event(0, EventType::PostCode, 1), event(4, EventType::Separator, 3), event(0, EventType::Separator, 2)];
+        let mut expected = BTreeMap::new();
+        // PCR 0 as if the separator had never been extended
+        expected.insert(0, sha1_extend(&[0; 20], &[1; 20]));
+        match verify_event_log(&events, &expected) {
+            Err(ReplayError::PcrMismatch { pcr_index: 0, event_index: Some(2), .. }) => {},
+            other => panic!("unexpected result {:?}", other.map(|replay| replay.pcr_values().clone()))
+        }
+    }
+
+    #[test]
+    fn verify_event_log_reports_pcr_without_events() {
+        let events = vec![event(0, EventType::PostCode, 1)];
+        let mut expected = BTreeMap::new();
+        expected.insert(5, vec![1; 20]);
+        match verify_event_log(&events, &expected) {
+            Err(ReplayError::PcrMismatch { pcr_index: 5, event_index: None, .. }) => {},
+            other => panic!("unexpected result {:?}", other.map(|replay| replay.pcr_values().clone()))
+        }
+    }
+
+    #[test]
+    fn verify_event_log_composite_checks_selected_pcrs() {
+        let events = vec![event(0, EventType::PostCode, 1), event(1, EventType::PostCode, 2), event(7, EventType::Separator, 3)];
+        let mut selected = BTreeMap::new();
+        selected.insert(0, sha1_extend(&[0; 20], &[1; 20]));
+        selected.insert(7, sha1_extend(&[0; 20], &[3; 20]));
+        let composite_hash = pcr_composite_hash(3, &selected).unwrap();
+        assert!(verify_event_log_composite(&events, 3, &[0, 7], &composite_hash).is_ok());
+        match verify_event_log_composite(&events, 3, &[0, 1], &composite_hash) {
+            Err(ReplayError::CompositeMismatch { .. }) => {},
+            other => panic!("unexpected result {:?}", other.map(|replay| replay.pcr_values().clone()))
+        }
+        match verify_event_log_composite(&events, 2, &[0, 23], &composite_hash) {
+            Err(ReplayError::PcrOutOfRange { pcr_index: 23, .. }) => {},
+            other => panic!("unexpected result {:?}", other.map(|replay| replay.pcr_values().clone()))
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use pubkey::RsaPublicKey;
+use verify::VerifyError;
+use TssValidation;
+
+pub const TPM_ORD_CERTIFY_SELF_TEST: u32 = 0x00000052;
+
+/// Returned by the TPM for almost every command once a self-test has
+/// failed.
+pub const TPM_E_FAILEDSELFTEST: u32 = 0x0000001c;
+
+/// What the TPM signs, with the nonce and ordinal, when its self-tests pass.
+pub const SELF_TEST_PASSED: &[u8] = b"Test Passed";
+
+/// The outcome of `TssTPM::self_test_full`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelfTestResult {
+    /// Whether the TPM completed its self-tests. A TPM that failed stays in
+    /// failure mode until it is reset.
+    pub passed: bool,
+    /// The vendor-specific `TPM_GetTestResult` output, useful for
+    /// diagnosing a failure.
+    pub data: Vec<u8>
+}
+
+/// The signed result of `TssTPM::certify_self_test`, verifiable without a
+/// TPM.
+///
+/// The TPM only signs if all of its self-tests pass, so a valid signature
+/// is itself the pass result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CertifiedSelfTest {
+    /// The data the signature covers, as reported by the TSS.
+    pub data: Vec<u8>,
+    pub signature: Vec<u8>
+}
+
+impl CertifiedSelfTest {
+    pub fn new(data: &[u8], signature: &[u8]) -> CertifiedSelfTest {
+        CertifiedSelfTest { data: data.to_vec(), signature: signature.to_vec() }
+    }
+
+    pub fn from_validation(validation: &TssValidation) -> CertifiedSelfTest {
+        CertifiedSelfTest::new(&validation.data, &validation.validation_data)
+    }
+
+    /// Checks that `key_public_key` signed a passed self-test for
+    /// `expected_nonce`.
+    pub fn verify(&self, key_public_key: &RsaPublicKey, expected_nonce: &[u8; 20]) -> Result<(), VerifyError> {
+        let expected = certified_self_test_data(expected_nonce);
+        if self.data != expected {
+            return Err(VerifyError::Mismatch("self-test data"));
+        }
+        key_public_key.verify_sha1(&expected, &self.signature)
+    }
+}
+
+/// The data signed by `TPM_CertifySelfTest`: the string "Test Passed", the
+/// anti-replay nonce and the command's ordinal.
+pub fn certified_self_test_data(nonce: &[u8; 20]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(SELF_TEST_PASSED.len() + 24);
+    data.extend_from_slice(SELF_TEST_PASSED);
+    data.extend_from_slice(nonce);
+    data.extend_from_slice(&TPM_ORD_CERTIFY_SELF_TEST.to_be_bytes());
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::{key_a, key_b, sign_sha1, signing_public_key};
+    use verify::VerifyError;
+    use super::*;
+
+    const NONCE: [u8; 20] = [0x6b; 20];
+
+    fn certified_self_test() -> CertifiedSelfTest {
+        let data = certified_self_test_data(&NONCE);
+        CertifiedSelfTest::new(&data, &sign_sha1(&key_a(), &data))
+    }
+
+    #[test]
+    fn encodes_signed_data() {
+        let data = certified_self_test_data(&NONCE);
+        assert_eq!(&data[..11], b"Test Passed");
+        assert_eq!(data[11..31], NONCE);
+        assert_eq!(data[31..], [0, 0, 0, 0x52]);
+    }
+
+    #[test]
+    fn verifies_certified_self_test() {
+        assert_eq!(certified_self_test().verify(&signing_public_key(&key_a()), &NONCE), Ok(()));
+    }
+
+    #[test]
+    fn rejects_other_key() {
+        assert_eq!(certified_self_test().verify(&signing_public_key(&key_b()), &NONCE), Err(VerifyError::BadSignature));
+    }
+
+    #[test]
+    fn rejects_other_nonce() {
+        assert_eq!(certified_self_test().verify(&signing_public_key(&key_a()), &[0; 20]), Err(VerifyError::Mismatch("self-test data")));
+    }
+
+    #[test]
+    fn rejects_signature_over_other_data() {
+        let mut self_test = certified_self_test();
+        self_test.signature = sign_sha1(&key_a(), b"Test Failed");
+        assert_eq!(self_test.verify(&signing_public_key(&key_a()), &NONCE), Err(VerifyError::BadSignature));
+    }
+}
@@ -0,0 +1,199 @@
+use std::error;
+use std::fmt;
+
+use TssError;
+
+pub const TSS_TPMSTATUS_DISABLEOWNERCLEAR: u32 = 0x00000001;
+pub const TSS_TPMSTATUS_DISABLEFORCECLEAR: u32 = 0x00000002;
+pub const TSS_TPMSTATUS_DISABLED: u32 = 0x00000003;
+pub const TSS_TPMSTATUS_DEACTIVATED: u32 = 0x00000004;
+pub const TSS_TPMSTATUS_OWNERSETDISABLE: u32 = 0x00000005;
+pub const TSS_TPMSTATUS_SETOWNERINSTALL: u32 = 0x00000006;
+pub const TSS_TPMSTATUS_DISABLEPUBEKREAD: u32 = 0x00000007;
+pub const TSS_TPMSTATUS_ALLOWMAINTENANCE: u32 = 0x00000008;
+pub const TSS_TPMSTATUS_PHYSPRES_LIFETIMELOCK: u32 = 0x00000009;
+pub const TSS_TPMSTATUS_PHYSPRES_HWENABLE: u32 = 0x0000000a;
+pub const TSS_TPMSTATUS_PHYSPRES_CMDENABLE: u32 = 0x0000000b;
+pub const TSS_TPMSTATUS_PHYSPRES_LOCK: u32 = 0x0000000c;
+pub const TSS_TPMSTATUS_PHYSPRESENCE: u32 = 0x0000000d;
+pub const TSS_TPMSTATUS_PHYSICALDISABLE: u32 = 0x0000000e;
+pub const TSS_TPMSTATUS_CEKP_USED: u32 = 0x0000000f;
+pub const TSS_TPMSTATUS_PHYSICALSETDEACTIVATED: u32 = 0x00000010;
+pub const TSS_TPMSTATUS_SETTEMPDEACTIVATED: u32 = 0x00000011;
+pub const TSS_TPMSTATUS_POSTINITIALISE: u32 = 0x00000012;
+pub const TSS_TPMSTATUS_TPMPOST: u32 = 0x00000013;
+pub const TSS_TPMSTATUS_TPMPOSTLOCK: u32 = 0x00000014;
+pub const TSS_TPMSTATUS_DISABLEPUBSRKREAD: u32 = 0x00000016;
+pub const TSS_TPMSTATUS_MAINTENANCEUSED: u32 = 0x00000017;
+pub const TSS_TPMSTATUS_OPERATORINSTALLED: u32 = 0x00000018;
+pub const TSS_TPMSTATUS_FIPS: u32 = 0x00000019;
+pub const TSS_TPMSTATUS_ENABLEREVOKEEK: u32 = 0x0000001a;
+pub const TSS_TPMSTATUS_NV_LOCK: u32 = 0x0000001b;
+pub const TSS_TPMSTATUS_TPM_ESTABLISHED: u32 = 0x0000001c;
+pub const TSS_TPMSTATUS_RESETLOCK: u32 = 0x0000001d;
+pub const TSS_TPMSTATUS_DISABLE_FULL_DA_LOGIC_INFO: u32 = 0x0000001e;
+
+/// Flags read with `TssTPM::get_status` and written with
+/// `TssTPM::set_status`.
+///
+/// TrouSerS reads most flags with `TPM_GetCapabilityOwner`, so reading
+/// generally needs owner authorization too. Each variant notes what setting
+/// it takes; see also `TpmStatusFlag::set_authorization`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TpmStatusFlag {
+    /// Owner authorization. Can only be set; once set, only a forced clear
+    /// can remove the owner.
+    DisableOwnerClear = TSS_TPMSTATUS_DISABLEOWNERCLEAR as isize,
+    /// No authorization. Can only be set, and lasts until the next reboot.
+    DisableForceClear = TSS_TPMSTATUS_DISABLEFORCECLEAR as isize,
+    /// Read only; use `OwnerSetDisable` or `PhysicalDisable`.
+    Disabled = TSS_TPMSTATUS_DISABLED as isize,
+    /// Read only; use `PhysicalSetDeactivated` or `SetTempDeactivated`.
+    Deactivated = TSS_TPMSTATUS_DEACTIVATED as isize,
+    /// Owner authorization. Enables or disables the TPM.
+    OwnerSetDisable = TSS_TPMSTATUS_OWNERSETDISABLE as isize,
+    /// Physical presence. Allows or forbids taking ownership.
+    SetOwnerInstall = TSS_TPMSTATUS_SETOWNERINSTALL as isize,
+    /// Owner authorization. Can only be set.
+    DisablePubekRead = TSS_TPMSTATUS_DISABLEPUBEKREAD as isize,
+    /// Owner authorization. Can only be cleared.
+    AllowMaintenance = TSS_TPMSTATUS_ALLOWMAINTENANCE as isize,
+    /// Physical presence command; usually set by firmware, permanently.
+    PhysPresLifetimeLock = TSS_TPMSTATUS_PHYSPRES_LIFETIMELOCK as isize,
+    /// Physical presence command; only before the lifetime lock is set.
+    PhysPresHwEnable = TSS_TPMSTATUS_PHYSPRES_HWENABLE as isize,
+    /// Physical presence command; only before the lifetime lock is set.
+    PhysPresCmdEnable = TSS_TPMSTATUS_PHYSPRES_CMDENABLE as isize,
+    /// Physical presence command. Locks physical presence until reboot.
+    PhysPresLock = TSS_TPMSTATUS_PHYSPRES_LOCK as isize,
+    /// Physical presence command. Asserts or deasserts physical presence.
+    PhysPresence = TSS_TPMSTATUS_PHYSPRESENCE as isize,
+    /// Physical presence. Enables or disables the TPM.
+    PhysicalDisable = TSS_TPMSTATUS_PHYSICALDISABLE as isize,
+    /// Read only.
+    CekpUsed = TSS_TPMSTATUS_CEKP_USED as isize,
+    /// Physical presence. Activates or deactivates the TPM from the next
+    /// reboot.
+    PhysicalSetDeactivated = TSS_TPMSTATUS_PHYSICALSETDEACTIVATED as isize,
+    /// Operator authorization or physical presence. Deactivates the TPM
+    /// until the next reboot; can only be set.
+    SetTempDeactivated = TSS_TPMSTATUS_SETTEMPDEACTIVATED as isize,
+    /// Read only.
+    PostInitialise = TSS_TPMSTATUS_POSTINITIALISE as isize,
+    /// Read only.
+    TpmPost = TSS_TPMSTATUS_TPMPOST as isize,
+    /// Read only.
+    TpmPostLock = TSS_TPMSTATUS_TPMPOSTLOCK as isize,
+    /// Owner authorization.
+    DisablePubSrkRead = TSS_TPMSTATUS_DISABLEPUBSRKREAD as isize,
+    /// Read only.
+    MaintenanceUsed = TSS_TPMSTATUS_MAINTENANCEUSED as isize,
+    /// Read only; installed with `TPM_SetOperatorAuth`.
+    OperatorInstalled = TSS_TPMSTATUS_OPERATORINSTALLED as isize,
+    /// Read only.
+    Fips = TSS_TPMSTATUS_FIPS as isize,
+    /// Read only; fixed when the endorsement key is created.
+    EnableRevokeEk = TSS_TPMSTATUS_ENABLEREVOKEEK as isize,
+    /// Read only.
+    NvLock = TSS_TPMSTATUS_NV_LOCK as isize,
+    /// Read only.
+    TpmEstablished = TSS_TPMSTATUS_TPM_ESTABLISHED as isize,
+    /// Owner authorization. Setting it resets the dictionary attack lock;
+    /// the state passed is ignored.
+    ResetLock = TSS_TPMSTATUS_RESETLOCK as isize,
+    /// Read only.
+    DisableFullDaLogicInfo = TSS_TPMSTATUS_DISABLE_FULL_DA_LOGIC_INFO as isize
+}
+
+/// Authorization `TssTPM::set_status` needs for a flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TpmStatusAuth {
+    None,
+    /// The owner secret, in the policy assigned to the TPM object.
+    Owner,
+    /// The operator secret, or physical presence if no operator is
+    /// installed.
+    OperatorOrPhysicalPresence,
+    /// Physical presence asserted, by hardware or by `PhysPresence`.
+    PhysicalPresence,
+    /// Sent with `TSC_PhysicalPresence`, which must be enabled and, for the
+    /// configuration flags, not yet lifetime locked.
+    PhysicalPresenceCommand,
+    /// The flag can't be set.
+    ReadOnly
+}
+
+impl fmt::Display for TpmStatusAuth {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match *self {
+            TpmStatusAuth::None => "no authorization",
+            TpmStatusAuth::Owner => "owner authorization",
+            TpmStatusAuth::OperatorOrPhysicalPresence => "operator authorization or physical presence",
+            TpmStatusAuth::PhysicalPresence => "physical presence",
+            TpmStatusAuth::PhysicalPresenceCommand => "physical presence commands to be enabled and unlocked",
+            TpmStatusAuth::ReadOnly => "a different command, as it is read only"
+        })
+    }
+}
+
+impl TpmStatusFlag {
+    pub fn set_authorization(&self) -> TpmStatusAuth {
+        match *self {
+            TpmStatusFlag::DisableForceClear => TpmStatusAuth::None,
+            TpmStatusFlag::DisableOwnerClear
+                | TpmStatusFlag::OwnerSetDisable
+                | TpmStatusFlag::DisablePubekRead
+                | TpmStatusFlag::AllowMaintenance
+                | TpmStatusFlag::DisablePubSrkRead
+                | TpmStatusFlag::ResetLock => TpmStatusAuth::Owner,
+            TpmStatusFlag::SetTempDeactivated => TpmStatusAuth::OperatorOrPhysicalPresence,
+            TpmStatusFlag::SetOwnerInstall
+                | TpmStatusFlag::PhysicalDisable
+                | TpmStatusFlag::PhysicalSetDeactivated => TpmStatusAuth::PhysicalPresence,
+            TpmStatusFlag::PhysPresLifetimeLock
+                | TpmStatusFlag::PhysPresHwEnable
+                | TpmStatusFlag::PhysPresCmdEnable
+                | TpmStatusFlag::PhysPresLock
+                | TpmStatusFlag::PhysPresence => TpmStatusAuth::PhysicalPresenceCommand,
+            TpmStatusFlag::Disabled
+                | TpmStatusFlag::Deactivated
+                | TpmStatusFlag::CekpUsed
+                | TpmStatusFlag::PostInitialise
+                | TpmStatusFlag::TpmPost
+                | TpmStatusFlag::TpmPostLock
+                | TpmStatusFlag::MaintenanceUsed
+                | TpmStatusFlag::OperatorInstalled
+                | TpmStatusFlag::Fips
+                | TpmStatusFlag::EnableRevokeEk
+                | TpmStatusFlag::NvLock
+                | TpmStatusFlag::TpmEstablished
+                | TpmStatusFlag::DisableFullDaLogicInfo => TpmStatusAuth::ReadOnly
+        }
+    }
+}
+
+/// Error returned by `TssTPM::set_status`, naming the authorization the
+/// flag needed.
+#[derive(Debug)]
+pub struct TpmStatusError {
+    pub flag: TpmStatusFlag,
+    pub required: TpmStatusAuth,
+    /// The TSS error, or `None` if the flag is read only and the TSS was
+    /// never called.
+    pub error: Option<TssError>
+}
+
+impl fmt::Display for TpmStatusError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.error {
+            Some(ref error) => write!(fmt, "setting {:?} requires {}: {}", self.flag, self.required, error),
+            None => write!(fmt, "{:?} cannot be set with SetStatus", self.flag)
+        }
+    }
+}
+
+impl error::Error for TpmStatusError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.error.as_ref().map(|error| error as &(dyn error::Error + 'static))
+    }
+}
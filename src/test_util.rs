@@ -0,0 +1,41 @@
+// Helpers shared by the unit tests
+
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey};
+use sha1::{Digest, Sha1};
+
+use pubkey::{RsaPublicKey, TPM_ES_NONE, TPM_SS_RSASSAPKCS1V15_SHA1};
+
+// Fixed 1024-bit keys standing in for TPM keys, small enough to keep the
+// tests fast
+const KEY_A: &str = include_str!("../testdata/test_key_a.pem");
+const KEY_B: &str = include_str!("../testdata/test_key_b.pem");
+const KEY_C: &str = include_str!("../testdata/test_key_c.pem");
+
+pub fn hex(hex: &str) -> Vec<u8> {
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+pub fn key_a() -> RsaPrivateKey {
+    RsaPrivateKey::from_pkcs1_pem(KEY_A).unwrap()
+}
+
+pub fn key_b() -> RsaPrivateKey {
+    RsaPrivateKey::from_pkcs1_pem(KEY_B).unwrap()
+}
+
+pub fn key_c() -> RsaPrivateKey {
+    RsaPrivateKey::from_pkcs1_pem(KEY_C).unwrap()
+}
+
+/// The key as a TPM signing key would report it.
+pub fn signing_public_key(key: &RsaPrivateKey) -> RsaPublicKey {
+    RsaPublicKey::from_rsa(&key.to_public_key(), TPM_ES_NONE, TPM_SS_RSASSAPKCS1V15_SHA1)
+}
+
+/// Signs `data` the way a `TPM_SS_RSASSAPKCS1v15_SHA1` key does.
+pub fn sign_sha1(key: &RsaPrivateKey, data: &[u8]) -> Vec<u8> {
+    key.sign(Pkcs1v15Sign::new::<Sha1>(), &Sha1::digest(data)).unwrap()
+}
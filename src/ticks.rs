@@ -0,0 +1,227 @@
+use std::error;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use trousers_sys::tspi::TPM_CURRENT_TICKS;
+
+use blob::{BlobReader, ParseError};
+
+pub const TPM_TAG_CURRENT_TICKS: u16 = 0x0014;
+
+/// A `TPM_CURRENT_TICKS`, the TPM's tick counter since its last reset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CurrentTicks {
+    pub current_ticks: u64,
+    /// Microseconds per tick.
+    pub tick_rate: u16,
+    /// Chosen afresh whenever the tick counter restarts.
+    pub tick_nonce: [u8; 20]
+}
+
+impl CurrentTicks {
+    pub fn parse(blob: &[u8]) -> Result<CurrentTicks, ParseError> {
+        let mut reader = BlobReader::new(blob);
+        if reader.read_u16()? != TPM_TAG_CURRENT_TICKS {
+            return Err(reader.invalid("TPM_CURRENT_TICKS.tag"));
+        }
+        let current_ticks = reader.read_u64()?;
+        let tick_rate = reader.read_u16()?;
+        let tick_nonce = reader.read_digest()?;
+        if !reader.is_empty() {
+            return Err(reader.invalid("TPM_CURRENT_TICKS"));
+        }
+        Ok(CurrentTicks { current_ticks, tick_rate, tick_nonce })
+    }
+
+    /// Whether both readings come from the same tick session, that is
+    /// without a TPM reset in between.
+    pub fn same_session(&self, other: &CurrentTicks) -> bool {
+        self.tick_nonce == other.tick_nonce
+    }
+}
+
+impl From<TPM_CURRENT_TICKS> for CurrentTicks {
+    fn from(ticks: TPM_CURRENT_TICKS) -> CurrentTicks {
+        CurrentTicks { current_ticks: ticks.currentTicks, tick_rate: ticks.tickRate, tick_nonce: ticks.tickNonce.nonce }
+    }
+}
+
+/// Error returned when tick readings can't be related to each other.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TickError {
+    /// The tick nonce changed: the TPM's tick counter restarted in between.
+    Reset,
+    /// The later reading doesn't have more ticks than the earlier one.
+    NotIncreasing,
+    /// The TPM reports a tick rate of 0.
+    ZeroTickRate,
+    /// The time is too far from the reference to be represented.
+    OutOfRange
+}
+
+impl fmt::Display for TickError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TickError::Reset => fmt.write_str("TPM tick counter was reset"),
+            TickError::NotIncreasing => fmt.write_str("tick readings are not increasing"),
+            TickError::ZeroTickRate => fmt.write_str("TPM tick rate is 0"),
+            TickError::OutOfRange => fmt.write_str("tick reading is out of the system clock's range")
+        }
+    }
+}
+
+impl error::Error for TickError {}
+
+/// Relates the TPM's tick counter to the system clock, to turn tick stamps
+/// into approximate timestamps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TickClock {
+    reference: CurrentTicks,
+    reference_time: SystemTime,
+    micros_per_tick: f64
+}
+
+impl TickClock {
+    /// Uses a single reading taken at `time` and the TPM's nominal tick
+    /// rate.
+    pub fn new(ticks: &CurrentTicks, time: SystemTime) -> Result<TickClock, TickError> {
+        if ticks.tick_rate == 0 {
+            return Err(TickError::ZeroTickRate);
+        }
+        Ok(TickClock { reference: ticks.clone(), reference_time: time, micros_per_tick: f64::from(ticks.tick_rate) })
+    }
+
+    /// Measures the tick rate from two readings and the system times they
+    /// were taken at, correcting for drift in the TPM's nominal rate.
+    pub fn calibrate(first: &CurrentTicks, first_time: SystemTime, second: &CurrentTicks, second_time: SystemTime) -> Result<TickClock, TickError> {
+        if !first.same_session(second) {
+            return Err(TickError::Reset);
+        }
+        if second.current_ticks <= first.current_ticks {
+            return Err(TickError::NotIncreasing);
+        }
+        let elapsed = second_time.duration_since(first_time).map_err(|_| TickError::NotIncreasing)?;
+        let micros_per_tick = elapsed.as_secs_f64() * 1e6 / (second.current_ticks - first.current_ticks) as f64;
+        Ok(TickClock { reference: first.clone(), reference_time: first_time, micros_per_tick })
+    }
+
+    pub fn micros_per_tick(&self) -> f64 {
+        self.micros_per_tick
+    }
+
+    /// Checks that `ticks` comes from the same tick session as the
+    /// reference reading.
+    pub fn check_session(&self, ticks: &CurrentTicks) -> Result<(), TickError> {
+        if !self.reference.same_session(ticks) {
+            return Err(TickError::Reset);
+        }
+        Ok(())
+    }
+
+    /// Estimates the system time at which the TPM's counter read `ticks`.
+    pub fn to_system_time(&self, ticks: &CurrentTicks) -> Result<SystemTime, TickError> {
+        self.check_session(ticks)?;
+        let (later, delta) = if ticks.current_ticks >= self.reference.current_ticks {
+            (true, ticks.current_ticks - self.reference.current_ticks)
+        } else {
+            (false, self.reference.current_ticks - ticks.current_ticks)
+        };
+        let offset = Duration::try_from_secs_f64(delta as f64 * self.micros_per_tick / 1e6)
+            .map_err(|_| TickError::OutOfRange)?;
+        let time = if later {
+            self.reference_time.checked_add(offset)
+        } else {
+            self.reference_time.checked_sub(offset)
+        };
+        time.ok_or(TickError::OutOfRange)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use blob::ParseError;
+    use test_util::hex;
+    use super::*;
+
+    // 0x100000 ticks at the nominal 1 microsecond per tick
+    fn current_ticks_blob() -> Vec<u8> {
+        let mut blob = hex("001400000000001000000001");
+        blob.extend_from_slice(&[0x5a; 20]);
+        blob
+    }
+
+    fn ticks(current_ticks: u64, tick_nonce: u8) -> CurrentTicks {
+        CurrentTicks { current_ticks, tick_rate: 1, tick_nonce: [tick_nonce; 20] }
+    }
+
+    fn reference_time() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    }
+
+    #[test]
+    fn parses_current_ticks() {
+        assert_eq!(CurrentTicks::parse(&current_ticks_blob()), Ok(ticks(0x100000, 0x5a)));
+    }
+
+    #[test]
+    fn rejects_malformed_current_ticks() {
+        let mut blob = current_ticks_blob();
+        assert_eq!(CurrentTicks::parse(&blob[..31]), Err(ParseError::UnexpectedEnd { offset: 12 }));
+        blob.push(0);
+        assert_eq!(CurrentTicks::parse(&blob), Err(ParseError::InvalidValue { offset: 32, field: "TPM_CURRENT_TICKS" }));
+        blob[1] = 0x15;
+        assert_eq!(CurrentTicks::parse(&blob), Err(ParseError::InvalidValue { offset: 2, field: "TPM_CURRENT_TICKS.tag" }));
+    }
+
+    #[test]
+    fn rejects_zero_tick_rate() {
+        let mut reference = ticks(1000, 1);
+        reference.tick_rate = 0;
+        assert_eq!(TickClock::new(&reference, reference_time()), Err(TickError::ZeroTickRate));
+    }
+
+    #[test]
+    fn calibrates_tick_rate() {
+        let clock = TickClock::calibrate(&ticks(1000, 1), reference_time(),
+                                         &ticks(3000, 1), reference_time() + Duration::from_millis(5)).unwrap();
+        assert_eq!(clock.micros_per_tick(), 2.5);
+        assert_eq!(clock.to_system_time(&ticks(5000, 1)), Ok(reference_time() + Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn rejects_bad_calibration() {
+        assert_eq!(TickClock::calibrate(&ticks(1000, 1), reference_time(), &ticks(3000, 2), reference_time()), Err(TickError::Reset));
+        assert_eq!(TickClock::calibrate(&ticks(1000, 1), reference_time(), &ticks(1000, 1), reference_time()), Err(TickError::NotIncreasing));
+        assert_eq!(TickClock::calibrate(&ticks(1000, 1), reference_time(), &ticks(3000, 1), reference_time() - Duration::from_secs(1)),
+                   Err(TickError::NotIncreasing));
+    }
+
+    #[test]
+    fn rejects_other_tick_session() {
+        let clock = TickClock::new(&ticks(1000, 1), reference_time()).unwrap();
+        assert_eq!(clock.check_session(&ticks(2000, 1)), Ok(()));
+        assert_eq!(clock.check_session(&ticks(2000, 2)), Err(TickError::Reset));
+        assert_eq!(clock.to_system_time(&ticks(2000, 2)), Err(TickError::Reset));
+    }
+
+    #[test]
+    fn converts_times_around_reference() {
+        let clock = TickClock::new(&ticks(1_000_000, 1), reference_time()).unwrap();
+        assert_eq!(clock.to_system_time(&ticks(1_000_000, 1)), Ok(reference_time()));
+        assert_eq!(clock.to_system_time(&ticks(3_000_000, 1)), Ok(reference_time() + Duration::from_secs(2)));
+        assert_eq!(clock.to_system_time(&ticks(500_000, 1)), Ok(reference_time() - Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn rejects_times_out_of_range() {
+        // 10^12 seconds per tick, so offsets overflow the system clock
+        let rate = Duration::from_secs(1_000_000_000_000);
+        let clock = TickClock::calibrate(&ticks(0, 1), reference_time(), &ticks(1, 1), reference_time() + rate).unwrap();
+        assert_eq!(clock.to_system_time(&ticks(100_000_000, 1)), Err(TickError::OutOfRange));
+        assert_eq!(clock.to_system_time(&ticks(15_000_000, 1)), Err(TickError::OutOfRange));
+        let clock = TickClock::calibrate(&ticks(15_000_000, 1), reference_time(), &ticks(15_000_001, 1), reference_time() + rate).unwrap();
+        assert_eq!(clock.to_system_time(&ticks(0, 1)), Err(TickError::OutOfRange));
+    }
+}
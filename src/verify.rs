@@ -0,0 +1,47 @@
+use std::error;
+use std::fmt;
+
+use blob::ParseError;
+
+/// Error returned when offline verification of TPM-signed data fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The signed data or the public key couldn't be decoded.
+    Parse(ParseError),
+    /// The public key isn't a usable RSA key.
+    InvalidKey,
+    /// The X.509 certificate couldn't be decoded or holds no RSA key.
+    InvalidCertificate,
+    /// The signature doesn't verify under the given public key.
+    BadSignature,
+    /// The named field of the signed structure doesn't hold the expected
+    /// value.
+    Mismatch(&'static str)
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VerifyError::Parse(ref error) => write!(fmt, "malformed data: {}", error),
+            VerifyError::InvalidKey => fmt.write_str("invalid RSA public key"),
+            VerifyError::InvalidCertificate => fmt.write_str("invalid X.509 certificate"),
+            VerifyError::BadSignature => fmt.write_str("signature verification failed"),
+            VerifyError::Mismatch(field) => write!(fmt, "{} does not match the expected value", field)
+        }
+    }
+}
+
+impl error::Error for VerifyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            VerifyError::Parse(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<ParseError> for VerifyError {
+    fn from(error: ParseError) -> VerifyError {
+        VerifyError::Parse(error)
+    }
+}